@@ -0,0 +1,1242 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use borsh::BorshDeserialize;
+use log::{info, warn};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcTransactionConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::account::from_account;
+use solana_sdk::clock::Clock;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::{hash, Hash};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::message::Message;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::sysvar;
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+/// Mirrors the program's `NATIVE_MINT`, marking a native SOL leg.
+pub const NATIVE_MINT: Pubkey = Pubkey::new_from_array([0u8; 32]);
+
+/// Byte offset of `Offer.pool` (right after the 8-byte discriminator).
+pub const OFFER_POOL_OFFSET: usize = 8;
+/// Byte offset of `Offer.fulfilled`.
+pub const OFFER_FULFILLED_OFFSET: usize = 8 + 32 + 32 + 8 + 32 + 32 + 8 * 4 + 8;
+
+/// Prefix of the on-chain `Offer` account. New program fields are appended after
+/// `escrow_bump`, so decoding only this prefix stays valid across upgrades.
+#[derive(BorshDeserialize, Clone, Debug)]
+pub struct OfferState {
+    pub pool: Pubkey,
+    pub maker: Pubkey,
+    pub offer_id: u64,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub original_amount_a: u64,
+    pub original_amount_b: u64,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub expiration_ts: i64,
+    pub fulfilled: bool,
+    pub bump: u8,
+    pub escrow_bump: u8,
+}
+
+impl OfferState {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || data[..8] != offer_discriminator() {
+            return Err(anyhow!("not an Offer account"));
+        }
+        Ok(Self::deserialize(&mut &data[8..])?)
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        !self.fulfilled && now > self.expiration_ts
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.mint_a == NATIVE_MINT
+    }
+}
+
+/// Mirrors the program's `CancellationFeePolicy`.
+#[derive(BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CancellationFeePolicy {
+    None,
+    Flat { amount: u64 },
+    Bps { bps: u16 },
+    AfterRestingTime { bps: u16, min_resting_secs: i64 },
+}
+
+/// Prefix of the on-chain `Pool` account, up to the cancellation fee policy.
+#[derive(BorshDeserialize, Clone, Debug)]
+pub struct PoolState {
+    pub authority: Pubkey,
+    pub legacy_max_partners: u8,
+    pub legacy_partners: Vec<Pubkey>,
+    pub whitelisted_mints: Vec<Pubkey>,
+    pub supported_pairs: Vec<(Pubkey, Pubkey)>,
+    pub paused: bool,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    pub min_swap_amount: u64,
+    pub max_expiration_secs: i64,
+    pub crank_bounty_lamports: u64,
+    pub swap_fee_policy: u8,
+    pub cancellation_fee_policy: CancellationFeePolicy,
+}
+
+impl PoolState {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || data[..8] != sighash("account:Pool") {
+            return Err(anyhow!("not a Pool account"));
+        }
+        Ok(Self::deserialize(&mut &data[8..])?)
+    }
+
+    /// Whether closing an offer may pay a cancellation fee, in which case the crank requires
+    /// the treasury's token account for the offer's mint.
+    pub fn charges_cancellation_fee(&self) -> bool {
+        self.cancellation_fee_policy != CancellationFeePolicy::None
+    }
+}
+
+/// The program's `ExpiredOffersCranked` event. `closed` leaves out offers the crank skipped
+/// because they were already filled, cancelled or closed by another keeper.
+#[derive(BorshDeserialize, Clone, Debug)]
+pub struct ExpiredOffersCranked {
+    pub keeper: Pubkey,
+    pub closed: u64,
+    pub bounty_paid: u64,
+}
+
+impl ExpiredOffersCranked {
+    /// Finds the event among a transaction's logs, where Anchor writes it as a base64
+    /// `Program data:` line.
+    pub fn from_logs(logs: &[String]) -> Option<Self> {
+        let discriminator = sighash("event:ExpiredOffersCranked");
+        logs.iter()
+            .filter_map(|line| line.strip_prefix("Program data: "))
+            .filter_map(|data| BASE64.decode(data).ok())
+            .find(|data| data.len() >= 8 && data[..8] == discriminator)
+            .and_then(|data| Self::deserialize(&mut &data[8..]).ok())
+    }
+}
+
+/// An expired offer together with the accounts the crank needs to close it. Native offers
+/// have no escrow token account or mint, so the maker fills those slots.
+#[derive(Clone, Debug)]
+pub struct ExpiredOffer {
+    pub address: Pubkey,
+    pub state: OfferState,
+    pub escrow: Pubkey,
+    pub maker_token_dest: Pubkey,
+    pub mint: Pubkey,
+    /// Where a cancellation fee would be paid: the treasury's token account for `mint_a`, or
+    /// the treasury wallet for native offers.
+    pub treasury_dest: Pubkey,
+    /// Program owning `mint_a`; `None` for native offers, which fit in any batch.
+    pub token_program: Option<Pubkey>,
+}
+
+/// Anchor account discriminator for `Offer`.
+pub fn offer_discriminator() -> [u8; 8] {
+    sighash("account:Offer")
+}
+
+fn sighash(preimage: &str) -> [u8; 8] {
+    let mut out = [0u8; 8];
+    out.copy_from_slice(&hash(preimage.as_bytes()).to_bytes()[..8]);
+    out
+}
+
+pub fn escrow_address(program_id: &Pubkey, offer: &Pubkey, escrow_bump: u8) -> Result<Pubkey> {
+    Ok(Pubkey::create_program_address(
+        &[b"escrow", offer.as_ref(), &[escrow_bump]],
+        program_id,
+    )?)
+}
+
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+/// The maker's `PartnerLimit` PDA for a mint. It only exists if a limit was set, but the crank
+/// always takes the address so it can release the offer's open escrow.
+pub fn partner_limit_address(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    partner: &Pubkey,
+    mint: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"partner_limit",
+            pool.as_ref(),
+            partner.as_ref(),
+            mint.as_ref(),
+        ],
+        program_id,
+    )
+    .0
+}
+
+/// Builds a `crank_expired_offers` instruction for a batch of offers whose mints are all
+/// owned by `token_program`. The mint is writable so withheld transfer fees can be
+/// harvested before the escrow closes. Mints with transfer hooks need their extra accounts
+/// appended after the groups, which this keeper does not resolve.
+pub fn crank_instruction(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    keeper: &Pubkey,
+    token_program: &Pubkey,
+    offers: &[ExpiredOffer],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*pool, false),
+        AccountMeta::new(*keeper, true),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new_readonly(*token_program, false),
+    ];
+    for offer in offers {
+        accounts.push(AccountMeta::new(offer.address, false));
+        accounts.push(AccountMeta::new(offer.escrow, false));
+        accounts.push(AccountMeta::new(offer.state.maker, false));
+        accounts.push(AccountMeta::new(offer.maker_token_dest, false));
+        accounts.push(AccountMeta::new(offer.mint, false));
+        accounts.push(AccountMeta::new(offer.treasury_dest, false));
+        accounts.push(AccountMeta::new(
+            partner_limit_address(program_id, pool, &offer.state.maker, &offer.state.mint_a),
+            false,
+        ));
+    }
+    let mut data = sighash("global:crank_expired_offers").to_vec();
+    data.push(offers.len() as u8);
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    }
+}
+
+/// Size of the signed transaction carrying a single crank instruction.
+pub fn crank_transaction_size(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    keeper: &Pubkey,
+    token_program: &Pubkey,
+    offers: &[ExpiredOffer],
+) -> usize {
+    let instruction = crank_instruction(program_id, pool, keeper, token_program, offers);
+    let message = Message::new(&[instruction], Some(keeper));
+    // Compact signature count, one signature per required signer, then the message.
+    1 + 64 * message.header.num_required_signatures as usize + message.serialize().len()
+}
+
+/// Splits offers sharing a token program into crank batches of at most `batch_size` offers.
+/// A batch is cut early when another offer would push the transaction past
+/// `PACKET_DATA_SIZE`, which happens quickly when the offers are on different mints.
+pub fn crank_batches<'a>(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    keeper: &Pubkey,
+    token_program: &Pubkey,
+    offers: &'a [ExpiredOffer],
+    batch_size: usize,
+) -> Vec<&'a [ExpiredOffer]> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    while start < offers.len() {
+        let mut end = start + 1;
+        while end < offers.len()
+            && end - start < batch_size
+            && crank_transaction_size(
+                program_id,
+                pool,
+                keeper,
+                token_program,
+                &offers[start..=end],
+            ) <= PACKET_DATA_SIZE
+        {
+            end += 1;
+        }
+        batches.push(&offers[start..end]);
+        start = end;
+    }
+    batches
+}
+
+/// `getProgramAccounts` filters selecting the unfulfilled offers of `pool`.
+pub fn open_offer_filters(pool: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, offer_discriminator().to_vec())),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+            OFFER_POOL_OFFSET,
+            pool.to_bytes().to_vec(),
+        )),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(OFFER_FULFILLED_OFFSET, vec![0])),
+    ]
+}
+
+/// Why a transaction was not confirmed.
+#[derive(Debug)]
+pub enum SendError {
+    /// The transaction failed, or failed simulation, and would fail the same way if resent.
+    Rejected(anyhow::Error),
+    /// The cluster could not be reached or the blockhash expired; resending may succeed.
+    Transient(anyhow::Error),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Rejected(err) => write!(f, "rejected: {err}"),
+            SendError::Transient(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+impl SendError {
+    /// Classifies a failed send by the transaction error it carries, if any.
+    pub fn from_transaction_error(err: Option<TransactionError>, source: anyhow::Error) -> Self {
+        match err {
+            None | Some(TransactionError::BlockhashNotFound) => SendError::Transient(source),
+            Some(_) => SendError::Rejected(source),
+        }
+    }
+}
+
+/// The cluster operations the keeper relies on. Implemented for `RpcClient` so the keeper
+/// runs against any RPC endpoint, including `solana-test-validator`; an in-process bank can
+/// be plugged in by implementing this trait.
+pub trait Cluster {
+    /// Returns every unfulfilled `Offer` account belonging to `pool`.
+    fn open_offers(&self, program_id: &Pubkey, pool: &Pubkey) -> Result<Vec<(Pubkey, Vec<u8>)>>;
+    fn unix_timestamp(&self) -> Result<i64>;
+    fn account_data(&self, key: &Pubkey) -> Result<Vec<u8>>;
+    /// Returns the owning program of each account, or `None` if it does not exist.
+    fn account_owners(&self, keys: &[Pubkey]) -> Result<Vec<Option<Pubkey>>>;
+    fn latest_blockhash(&self) -> Result<Hash>;
+    fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> std::result::Result<Signature, SendError>;
+    /// Returns the log messages of a confirmed transaction.
+    fn transaction_logs(&self, signature: &Signature) -> Result<Vec<String>>;
+}
+
+impl Cluster for RpcClient {
+    fn open_offers(&self, program_id: &Pubkey, pool: &Pubkey) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(open_offer_filters(pool)),
+            account_config: RpcAccountInfoConfig {
+                commitment: Some(self.commitment()),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        Ok(self
+            .get_program_accounts_with_config(program_id, config)?
+            .into_iter()
+            .map(|(key, account)| (key, account.data))
+            .collect())
+    }
+
+    fn unix_timestamp(&self) -> Result<i64> {
+        let account = self.get_account(&sysvar::clock::id())?;
+        let clock: Clock =
+            from_account(&account).ok_or_else(|| anyhow!("failed to decode clock sysvar"))?;
+        Ok(clock.unix_timestamp)
+    }
+
+    fn account_data(&self, key: &Pubkey) -> Result<Vec<u8>> {
+        Ok(self.get_account_data(key)?)
+    }
+
+    fn account_owners(&self, keys: &[Pubkey]) -> Result<Vec<Option<Pubkey>>> {
+        let mut owners = Vec::with_capacity(keys.len());
+        // getMultipleAccounts accepts at most 100 keys per request.
+        for chunk in keys.chunks(100) {
+            owners.extend(
+                self.get_multiple_accounts(chunk)?
+                    .iter()
+                    .map(|account| account.as_ref().map(|account| account.owner)),
+            );
+        }
+        Ok(owners)
+    }
+
+    fn latest_blockhash(&self) -> Result<Hash> {
+        Ok(self.get_latest_blockhash()?)
+    }
+
+    fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> std::result::Result<Signature, SendError> {
+        self.send_and_confirm_transaction(transaction)
+            .map_err(|err| {
+                SendError::from_transaction_error(err.get_transaction_error(), err.into())
+            })
+    }
+
+    fn transaction_logs(&self, signature: &Signature) -> Result<Vec<String>> {
+        let config = RpcTransactionConfig {
+            commitment: Some(self.commitment()),
+            max_supported_transaction_version: Some(0),
+            ..RpcTransactionConfig::default()
+        };
+        let meta = self
+            .get_transaction_with_config(signature, config)?
+            .transaction
+            .meta
+            .ok_or_else(|| anyhow!("transaction {signature} has no status meta"))?;
+        Ok(Option::from(meta.log_messages).unwrap_or_default())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeeperConfig {
+    pub program_id: Pubkey,
+    pub pool: Pubkey,
+    /// Most offers closed per transaction; each one adds seven accounts to the crank, so
+    /// batches are cut earlier when they would not fit in a transaction.
+    pub batch_size: usize,
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+pub struct Keeper<C: Cluster> {
+    pub cluster: C,
+    pub payer: Keypair,
+    pub config: KeeperConfig,
+    /// Offers the program rejected on their own; later scans skip them instead of failing
+    /// the same way again.
+    rejected: RefCell<HashSet<Pubkey>>,
+}
+
+impl<C: Cluster> Keeper<C> {
+    pub fn new(cluster: C, payer: Keypair, config: KeeperConfig) -> Self {
+        Self {
+            cluster,
+            payer,
+            config,
+            rejected: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Finds offers that have expired and can be closed by the crank. Offers whose mint is not
+    /// owned by a token program, whose maker has no token account for `mint_a`, or whose
+    /// treasury token account is missing while the pool charges a cancellation fee are
+    /// skipped, since the crank would fail the batch. So are offers the program already
+    /// rejected in an earlier scan.
+    pub fn find_expired_offers(&self) -> Result<Vec<ExpiredOffer>> {
+        let now = self.cluster.unix_timestamp()?;
+        let pool = PoolState::decode(&self.cluster.account_data(&self.config.pool)?)?;
+        let treasury = pool.treasury;
+        let mut expired = Vec::new();
+        for (address, data) in self
+            .cluster
+            .open_offers(&self.config.program_id, &self.config.pool)?
+        {
+            let state = match OfferState::decode(&data) {
+                Ok(state) => state,
+                Err(err) => {
+                    warn!("skipping undecodable offer {address}: {err}");
+                    continue;
+                }
+            };
+            if state.is_expired(now) && !self.rejected.borrow().contains(&address) {
+                expired.push((address, state));
+            }
+        }
+
+        let mut mints: Vec<Pubkey> = expired
+            .iter()
+            .filter(|(_, state)| !state.is_native())
+            .map(|(_, state)| state.mint_a)
+            .collect();
+        mints.sort();
+        mints.dedup();
+        let mint_owners: HashMap<Pubkey, Option<Pubkey>> = mints
+            .iter()
+            .copied()
+            .zip(self.cluster.account_owners(&mints)?)
+            .collect();
+
+        let mut candidates = Vec::with_capacity(expired.len());
+        for (address, state) in expired {
+            if state.is_native() {
+                candidates.push(ExpiredOffer {
+                    address,
+                    escrow: state.maker,
+                    maker_token_dest: state.maker,
+                    mint: state.maker,
+                    treasury_dest: treasury,
+                    token_program: None,
+                    state,
+                });
+                continue;
+            }
+            let token_program = match mint_owners.get(&state.mint_a).copied().flatten() {
+                Some(owner) if owner == TOKEN_PROGRAM_ID || owner == TOKEN_2022_PROGRAM_ID => owner,
+                _ => {
+                    warn!(
+                        "skipping offer {address}: mint {} is not a token mint",
+                        state.mint_a
+                    );
+                    continue;
+                }
+            };
+            candidates.push(ExpiredOffer {
+                address,
+                escrow: escrow_address(&self.config.program_id, &address, state.escrow_bump)?,
+                maker_token_dest: associated_token_address(
+                    &state.maker,
+                    &state.mint_a,
+                    &token_program,
+                ),
+                mint: state.mint_a,
+                treasury_dest: associated_token_address(&treasury, &state.mint_a, &token_program),
+                token_program: Some(token_program),
+                state,
+            });
+        }
+
+        let destinations: Vec<Pubkey> = candidates.iter().map(|o| o.maker_token_dest).collect();
+        let owners = self.cluster.account_owners(&destinations)?;
+        let treasury_missing: Vec<bool> = if pool.charges_cancellation_fee() {
+            let treasury_dests: Vec<Pubkey> = candidates.iter().map(|o| o.treasury_dest).collect();
+            let treasury_owners = self.cluster.account_owners(&treasury_dests)?;
+            treasury_owners.iter().map(Option::is_none).collect()
+        } else {
+            vec![false; candidates.len()]
+        };
+        let mut closable = Vec::with_capacity(candidates.len());
+        for ((offer, owner), treasury_missing) in
+            candidates.into_iter().zip(owners).zip(treasury_missing)
+        {
+            if offer.state.is_native() {
+                closable.push(offer);
+            } else if owner.is_none() {
+                warn!(
+                    "skipping offer {}: maker {} has no token account for {}",
+                    offer.address, offer.state.maker, offer.state.mint_a
+                );
+            } else if treasury_missing {
+                warn!(
+                    "skipping offer {}: treasury {treasury} has no token account for {}",
+                    offer.address, offer.state.mint_a
+                );
+            } else {
+                closable.push(offer);
+            }
+        }
+        Ok(closable)
+    }
+
+    /// Runs one scan and submits crank transactions in batches, one token program per batch.
+    /// A batch the program rejects is resubmitted offer by offer, so one bad offer does not
+    /// hold up the rest; an offer rejected on its own is skipped by later scans. Batches that
+    /// keep failing for transient reasons are left for the next scan. Returns the number of
+    /// offers the crank reported closing.
+    pub fn run_once(&self) -> Result<usize> {
+        let expired = self.find_expired_offers()?;
+        if expired.is_empty() {
+            info!("no expired offers");
+            return Ok(0);
+        }
+        info!("found {} expired offers", expired.len());
+
+        // Native offers ignore the token program, so they ride along with the classic batch.
+        let mut by_program: BTreeMap<Pubkey, Vec<ExpiredOffer>> = BTreeMap::new();
+        for offer in expired {
+            by_program
+                .entry(offer.token_program.unwrap_or(TOKEN_PROGRAM_ID))
+                .or_default()
+                .push(offer);
+        }
+
+        let mut closed = 0;
+        for (token_program, offers) in &by_program {
+            for batch in crank_batches(
+                &self.config.program_id,
+                &self.config.pool,
+                &self.payer.pubkey(),
+                token_program,
+                offers,
+                self.config.batch_size,
+            ) {
+                match self.crank(token_program, batch) {
+                    Ok(count) => closed += count,
+                    Err(err @ SendError::Rejected(_)) if batch.len() > 1 => {
+                        warn!(
+                            "batch of {} offers failed: {err}; retrying individually",
+                            batch.len()
+                        );
+                        for offer in batch {
+                            closed += self.crank_one(token_program, offer);
+                        }
+                    }
+                    Err(SendError::Rejected(err)) => self.reject(&batch[0], err),
+                    Err(SendError::Transient(err)) => {
+                        warn!(
+                            "batch of {} offers failed: {err}; leaving it for the next scan",
+                            batch.len()
+                        )
+                    }
+                }
+            }
+        }
+        Ok(closed)
+    }
+
+    fn crank_one(&self, token_program: &Pubkey, offer: &ExpiredOffer) -> usize {
+        match self.crank(token_program, std::slice::from_ref(offer)) {
+            Ok(count) => count,
+            Err(SendError::Rejected(err)) => {
+                self.reject(offer, err);
+                0
+            }
+            Err(SendError::Transient(err)) => {
+                warn!(
+                    "offer {} failed: {err}; leaving it for the next scan",
+                    offer.address
+                );
+                0
+            }
+        }
+    }
+
+    fn reject(&self, offer: &ExpiredOffer, err: anyhow::Error) {
+        warn!(
+            "offer {} rejected: {err}; skipping it from now on",
+            offer.address
+        );
+        self.rejected.borrow_mut().insert(offer.address);
+    }
+
+    /// Submits one crank and returns how many offers it closed, read from its
+    /// `ExpiredOffersCranked` event. A confirmed crank whose logs cannot be read counts as
+    /// closing nothing rather than failing, so its offers are not resubmitted.
+    fn crank(
+        &self,
+        token_program: &Pubkey,
+        offers: &[ExpiredOffer],
+    ) -> std::result::Result<usize, SendError> {
+        let signature = self.send_with_retry(crank_instruction(
+            &self.config.program_id,
+            &self.config.pool,
+            &self.payer.pubkey(),
+            token_program,
+            offers,
+        ))?;
+        let event = match self.cluster.transaction_logs(&signature) {
+            Ok(logs) => ExpiredOffersCranked::from_logs(&logs),
+            Err(err) => {
+                warn!("failed to fetch logs of {signature}: {err}");
+                None
+            }
+        };
+        let closed = match event {
+            Some(event) => event.closed as usize,
+            None => {
+                warn!("no ExpiredOffersCranked event in {signature}");
+                0
+            }
+        };
+        info!("closed {closed} of {} offers in {signature}", offers.len());
+        Ok(closed)
+    }
+
+    /// Signs with a fresh blockhash on every attempt and backs off exponentially between
+    /// transient failures. A transaction the program rejects is not retried.
+    fn send_with_retry(
+        &self,
+        instruction: Instruction,
+    ) -> std::result::Result<Signature, SendError> {
+        let mut backoff = self.config.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let blockhash = self
+                .cluster
+                .latest_blockhash()
+                .map_err(SendError::Transient)?;
+            let transaction = Transaction::new_signed_with_payer(
+                std::slice::from_ref(&instruction),
+                Some(&self.payer.pubkey()),
+                &[&self.payer],
+                blockhash,
+            );
+            match self.cluster.send_transaction(&transaction) {
+                Ok(signature) => return Ok(signature),
+                Err(SendError::Transient(err)) if attempt < self.config.max_retries => {
+                    warn!("attempt {attempt} failed: {err}; retrying in {backoff:?}");
+                    sleep(backoff);
+                    backoff = (backoff * 2).min(self.config.max_backoff);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+pub fn rpc_client(url: String) -> RpcClient {
+    RpcClient::new_with_commitment(url, CommitmentConfig::confirmed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashSet;
+
+    use borsh::BorshSerialize;
+
+    use super::*;
+
+    const PROGRAM_ID: Pubkey = pubkey!("9s97f1eHD71SCRWCFVucTdEUPwwHEcPxWV9fDqE67EME");
+    const NOW: i64 = 1_700_000_000;
+
+    /// Full layout of the program's `Offer`, including the fields the keeper does not decode.
+    #[derive(BorshSerialize)]
+    struct Offer {
+        pool: Pubkey,
+        maker: Pubkey,
+        offer_id: u64,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        original_amount_a: u64,
+        original_amount_b: u64,
+        amount_a: u64,
+        amount_b: u64,
+        expiration_ts: i64,
+        fulfilled: bool,
+        bump: u8,
+        escrow_bump: u8,
+        decimals_a: u8,
+        decimals_b: u8,
+        created_ts: i64,
+        allowed_takers: Vec<Pubkey>,
+        all_or_none: bool,
+        min_fill_b: u64,
+        max_fills: u16,
+        fill_count: u16,
+        sequence: u64,
+        extension_count: u8,
+        crank_deposit: u64,
+    }
+
+    fn offer(pool: Pubkey, mint_a: Pubkey, fulfilled: bool) -> Offer {
+        Offer {
+            pool,
+            maker: Pubkey::new_unique(),
+            offer_id: 7,
+            mint_a,
+            mint_b: Pubkey::new_unique(),
+            original_amount_a: 1_000,
+            original_amount_b: 2_000,
+            amount_a: 600,
+            amount_b: 1_200,
+            expiration_ts: NOW - 10,
+            fulfilled,
+            bump: 254,
+            escrow_bump: 255,
+            decimals_a: 6,
+            decimals_b: 9,
+            created_ts: NOW - 100,
+            allowed_takers: vec![Pubkey::new_unique()],
+            all_or_none: false,
+            min_fill_b: 10,
+            max_fills: 3,
+            fill_count: 1,
+            sequence: 2,
+            extension_count: 1,
+            crank_deposit: 10_000,
+        }
+    }
+
+    fn account_data(discriminator: [u8; 8], value: &impl BorshSerialize) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        data.extend(borsh::to_vec(value).unwrap());
+        data
+    }
+
+    /// A `Pool` prefix; `flat_cancellation_fee` selects `CancellationFeePolicy::Flat`.
+    fn pool_data(treasury: Pubkey, flat_cancellation_fee: Option<u64>) -> Vec<u8> {
+        let prefix = (
+            Pubkey::new_unique(),
+            0u8,
+            Vec::<Pubkey>::new(),
+            Vec::<Pubkey>::new(),
+            Vec::<(Pubkey, Pubkey)>::new(),
+            false,
+            100u16,
+            treasury,
+            1u64,
+            86_400i64,
+            0u64,
+            0u8,
+        );
+        let mut data = account_data(sighash("account:Pool"), &prefix);
+        match flat_cancellation_fee {
+            Some(amount) => {
+                data.push(1);
+                data.extend(amount.to_le_bytes());
+            }
+            None => data.push(0),
+        }
+        data
+    }
+
+    /// Offer PDA whose escrow address can be derived with its stored bump.
+    fn offer_address(offer: &mut Offer) -> Pubkey {
+        loop {
+            let address = Pubkey::new_unique();
+            if let Some((_, bump)) =
+                Pubkey::try_find_program_address(&[b"escrow", address.as_ref()], &PROGRAM_ID)
+            {
+                offer.escrow_bump = bump;
+                return address;
+            }
+        }
+    }
+
+    struct MockCluster {
+        pool: Pubkey,
+        treasury: Pubkey,
+        pool_data: Vec<u8>,
+        offers: Vec<(Pubkey, Vec<u8>)>,
+        /// Offers a successful crank closed; they no longer show up as open.
+        closed: RefCell<HashSet<Pubkey>>,
+        owners: HashMap<Pubkey, Pubkey>,
+        /// Offers whose crank always fails.
+        poisoned: HashSet<Pubkey>,
+        /// Offers the program skips, e.g. because another keeper closed them first.
+        skipped: HashSet<Pubkey>,
+        /// Sends that fail before any succeeds.
+        transient_failures: Cell<u32>,
+        /// Offers in each attempted crank, in order.
+        attempts: RefCell<Vec<Vec<Pubkey>>>,
+        logs: RefCell<HashMap<Signature, Vec<String>>>,
+    }
+
+    impl MockCluster {
+        fn new() -> Self {
+            let treasury = Pubkey::new_unique();
+            Self {
+                pool: Pubkey::new_unique(),
+                treasury,
+                pool_data: pool_data(treasury, None),
+                offers: Vec::new(),
+                closed: RefCell::new(HashSet::new()),
+                owners: HashMap::new(),
+                poisoned: HashSet::new(),
+                skipped: HashSet::new(),
+                transient_failures: Cell::new(0),
+                attempts: RefCell::new(Vec::new()),
+                logs: RefCell::new(HashMap::new()),
+            }
+        }
+
+        /// Adds an expired token offer on a fresh mint whose maker holds a token account.
+        fn add_token_offer(&mut self) -> Pubkey {
+            let mint = Pubkey::new_unique();
+            self.owners.insert(mint, TOKEN_PROGRAM_ID);
+            let mut state = offer(self.pool, mint, false);
+            let address = offer_address(&mut state);
+            self.owners.insert(
+                associated_token_address(&state.maker, &mint, &TOKEN_PROGRAM_ID),
+                TOKEN_PROGRAM_ID,
+            );
+            self.offers
+                .push((address, account_data(offer_discriminator(), &state)));
+            address
+        }
+
+        fn add_native_offer(&mut self) -> Pubkey {
+            let mut state = offer(self.pool, NATIVE_MINT, false);
+            let address = offer_address(&mut state);
+            self.offers
+                .push((address, account_data(offer_discriminator(), &state)));
+            address
+        }
+    }
+
+    impl Cluster for &MockCluster {
+        fn open_offers(
+            &self,
+            _program_id: &Pubkey,
+            _pool: &Pubkey,
+        ) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+            let closed = self.closed.borrow();
+            Ok(self
+                .offers
+                .iter()
+                .filter(|(address, _)| !closed.contains(address))
+                .cloned()
+                .collect())
+        }
+
+        fn unix_timestamp(&self) -> Result<i64> {
+            Ok(NOW)
+        }
+
+        fn account_data(&self, key: &Pubkey) -> Result<Vec<u8>> {
+            assert_eq!(*key, self.pool);
+            Ok(self.pool_data.clone())
+        }
+
+        fn account_owners(&self, keys: &[Pubkey]) -> Result<Vec<Option<Pubkey>>> {
+            Ok(keys
+                .iter()
+                .map(|key| self.owners.get(key).copied())
+                .collect())
+        }
+
+        fn latest_blockhash(&self) -> Result<Hash> {
+            Ok(Hash::default())
+        }
+
+        fn send_transaction(
+            &self,
+            transaction: &Transaction,
+        ) -> std::result::Result<Signature, SendError> {
+            assert!(transaction.message.serialize().len() + 65 <= PACKET_DATA_SIZE);
+            let instruction = &transaction.message.instructions[0];
+            let offers: Vec<Pubkey> = instruction.accounts[4..]
+                .chunks(7)
+                .map(|group| transaction.message.account_keys[group[0] as usize])
+                .collect();
+            self.attempts.borrow_mut().push(offers.clone());
+
+            if self.transient_failures.get() > 0 {
+                self.transient_failures
+                    .set(self.transient_failures.get() - 1);
+                return Err(SendError::Transient(anyhow!("blockhash not found")));
+            }
+            if offers.iter().any(|offer| self.poisoned.contains(offer)) {
+                return Err(SendError::Rejected(anyhow!("custom program error")));
+            }
+            self.closed.borrow_mut().extend(offers.iter().copied());
+
+            let closed = offers.iter().filter(|o| !self.skipped.contains(o)).count() as u64;
+            let mut event = sighash("event:ExpiredOffersCranked").to_vec();
+            event.extend(Pubkey::new_unique().to_bytes());
+            event.extend(closed.to_le_bytes());
+            event.extend(0u64.to_le_bytes());
+            let signature = Signature::new_unique();
+            self.logs.borrow_mut().insert(
+                signature,
+                vec![
+                    format!("Program {PROGRAM_ID} invoke [1]"),
+                    format!("Program data: {}", BASE64.encode(event)),
+                    format!("Program {PROGRAM_ID} success"),
+                ],
+            );
+            Ok(signature)
+        }
+
+        fn transaction_logs(&self, signature: &Signature) -> Result<Vec<String>> {
+            Ok(self.logs.borrow()[signature].clone())
+        }
+    }
+
+    fn keeper(cluster: &MockCluster, batch_size: usize) -> Keeper<&MockCluster> {
+        Keeper::new(
+            cluster,
+            Keypair::new(),
+            KeeperConfig {
+                program_id: PROGRAM_ID,
+                pool: cluster.pool,
+                batch_size,
+                max_retries: 3,
+                initial_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            },
+        )
+    }
+
+    #[test]
+    fn decodes_serialized_offer() {
+        let pool = Pubkey::new_unique();
+        let state = offer(pool, Pubkey::new_unique(), true);
+        let data = account_data(offer_discriminator(), &state);
+
+        let decoded = OfferState::decode(&data).unwrap();
+        assert_eq!(decoded.pool, pool);
+        assert_eq!(decoded.maker, state.maker);
+        assert_eq!(decoded.offer_id, state.offer_id);
+        assert_eq!(decoded.mint_a, state.mint_a);
+        assert_eq!(decoded.mint_b, state.mint_b);
+        assert_eq!(decoded.original_amount_a, state.original_amount_a);
+        assert_eq!(decoded.original_amount_b, state.original_amount_b);
+        assert_eq!(decoded.amount_a, state.amount_a);
+        assert_eq!(decoded.amount_b, state.amount_b);
+        assert_eq!(decoded.expiration_ts, state.expiration_ts);
+        assert!(decoded.fulfilled);
+        assert_eq!(decoded.bump, state.bump);
+        assert_eq!(decoded.escrow_bump, state.escrow_bump);
+
+        assert_eq!(
+            &data[OFFER_POOL_OFFSET..OFFER_POOL_OFFSET + 32],
+            pool.as_ref()
+        );
+        assert_eq!(data[OFFER_FULFILLED_OFFSET], 1);
+        let open = account_data(
+            offer_discriminator(),
+            &offer(pool, Pubkey::new_unique(), false),
+        );
+        assert_eq!(open[OFFER_FULFILLED_OFFSET], 0);
+    }
+
+    #[test]
+    fn rejects_other_accounts() {
+        let state = offer(Pubkey::new_unique(), Pubkey::new_unique(), false);
+        assert!(OfferState::decode(&account_data(sighash("account:Pool"), &state)).is_err());
+        assert!(OfferState::decode(&[0u8; 4]).is_err());
+    }
+
+    #[test]
+    fn crank_instruction_layout() {
+        let mut cluster = MockCluster::new();
+        cluster.add_token_offer();
+        cluster.add_native_offer();
+        let treasury = PoolState::decode(&cluster.pool_data).unwrap().treasury;
+        let keeper = keeper(&cluster, 5);
+        let offers = keeper.find_expired_offers().unwrap();
+        assert_eq!(offers.len(), 2);
+
+        let payer = keeper.payer.pubkey();
+        let ix = crank_instruction(
+            &PROGRAM_ID,
+            &cluster.pool,
+            &payer,
+            &TOKEN_PROGRAM_ID,
+            &offers,
+        );
+        assert_eq!(ix.program_id, PROGRAM_ID);
+        assert_eq!(ix.accounts.len(), 4 + 7 * 2);
+        assert_eq!(ix.accounts[0], AccountMeta::new(cluster.pool, false));
+        assert_eq!(ix.accounts[1], AccountMeta::new(payer, true));
+        assert_eq!(
+            ix.accounts[2],
+            AccountMeta::new_readonly(sysvar::clock::id(), false)
+        );
+        assert_eq!(
+            ix.accounts[3],
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false)
+        );
+        let mut data = sighash("global:crank_expired_offers").to_vec();
+        data.push(2);
+        assert_eq!(ix.data, data);
+
+        for (offer, group) in offers.iter().zip(ix.accounts[4..].chunks(7)) {
+            let state = &offer.state;
+            let keys: Vec<Pubkey> = group.iter().map(|meta| meta.pubkey).collect();
+            assert!(group.iter().all(|meta| meta.is_writable && !meta.is_signer));
+            assert_eq!(keys[0], offer.address);
+            assert_eq!(keys[2], state.maker);
+            assert_eq!(
+                keys[6],
+                partner_limit_address(&PROGRAM_ID, &cluster.pool, &state.maker, &state.mint_a)
+            );
+            if state.is_native() {
+                assert_eq!(&keys[1..5], &[state.maker; 4][..]);
+                assert_eq!(keys[5], treasury);
+            } else {
+                assert_eq!(
+                    keys[1],
+                    escrow_address(&PROGRAM_ID, &offer.address, state.escrow_bump).unwrap()
+                );
+                assert_eq!(
+                    keys[3],
+                    associated_token_address(&state.maker, &state.mint_a, &TOKEN_PROGRAM_ID)
+                );
+                assert_eq!(keys[4], state.mint_a);
+                assert_eq!(
+                    keys[5],
+                    associated_token_address(&treasury, &state.mint_a, &TOKEN_PROGRAM_ID)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn batches_fit_in_a_transaction() {
+        let mut cluster = MockCluster::new();
+        for _ in 0..5 {
+            cluster.add_token_offer();
+        }
+        let keeper = keeper(&cluster, 5);
+        let offers = keeper.find_expired_offers().unwrap();
+        let payer = keeper.payer.pubkey();
+        assert!(
+            crank_transaction_size(
+                &PROGRAM_ID,
+                &cluster.pool,
+                &payer,
+                &TOKEN_PROGRAM_ID,
+                &offers
+            ) > PACKET_DATA_SIZE
+        );
+
+        let batches = crank_batches(
+            &PROGRAM_ID,
+            &cluster.pool,
+            &payer,
+            &TOKEN_PROGRAM_ID,
+            &offers,
+            5,
+        );
+        assert!(batches.len() > 1);
+        assert_eq!(batches.iter().map(|b| b.len()).sum::<usize>(), 5);
+        for batch in batches {
+            assert!(
+                crank_transaction_size(
+                    &PROGRAM_ID,
+                    &cluster.pool,
+                    &payer,
+                    &TOKEN_PROGRAM_ID,
+                    batch
+                ) <= PACKET_DATA_SIZE
+            );
+        }
+        assert_eq!(keeper.run_once().unwrap(), 5);
+    }
+
+    #[test]
+    fn batches_respect_batch_size() {
+        let mut cluster = MockCluster::new();
+        for _ in 0..5 {
+            cluster.add_native_offer();
+        }
+        let keeper = keeper(&cluster, 2);
+        let offers = keeper.find_expired_offers().unwrap();
+        let sizes: Vec<usize> = crank_batches(
+            &PROGRAM_ID,
+            &cluster.pool,
+            &keeper.payer.pubkey(),
+            &TOKEN_PROGRAM_ID,
+            &offers,
+            2,
+        )
+        .iter()
+        .map(|batch| batch.len())
+        .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        let mut cluster = MockCluster::new();
+        cluster.add_native_offer();
+        cluster.add_native_offer();
+        cluster.transient_failures.set(2);
+
+        assert_eq!(keeper(&cluster, 5).run_once().unwrap(), 2);
+        let attempts = cluster.attempts.borrow();
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts.iter().all(|offers| offers.len() == 2));
+    }
+
+    #[test]
+    fn splits_failing_batch() {
+        let mut cluster = MockCluster::new();
+        let first = cluster.add_native_offer();
+        let bad = cluster.add_native_offer();
+        let last = cluster.add_native_offer();
+        cluster.poisoned.insert(bad);
+
+        let keeper = keeper(&cluster, 5);
+        assert_eq!(keeper.run_once().unwrap(), 2);
+        // A rejected batch is not retried as a whole; it is split into single offers at once.
+        assert_eq!(
+            *cluster.attempts.borrow(),
+            vec![vec![first, bad, last], vec![first], vec![bad], vec![last]]
+        );
+
+        // The next scan skips the offer the program rejected instead of failing on it again.
+        assert_eq!(keeper.run_once().unwrap(), 0);
+        assert_eq!(cluster.attempts.borrow().len(), 4);
+    }
+
+    #[test]
+    fn leaves_persistent_transient_failures_for_next_scan() {
+        let mut cluster = MockCluster::new();
+        cluster.add_native_offer();
+        cluster.add_native_offer();
+        cluster.transient_failures.set(4);
+
+        let keeper = keeper(&cluster, 5);
+        assert_eq!(keeper.run_once().unwrap(), 0);
+        // max_retries attempts of the whole batch, without splitting it.
+        assert_eq!(cluster.attempts.borrow().len(), 3);
+        assert!(cluster
+            .attempts
+            .borrow()
+            .iter()
+            .all(|offers| offers.len() == 2));
+
+        assert_eq!(keeper.run_once().unwrap(), 2);
+    }
+
+    #[test]
+    fn skips_offers_without_treasury_token_account_when_fee_applies() {
+        let mut cluster = MockCluster::new();
+        cluster.pool_data = pool_data(cluster.treasury, Some(5));
+        let missing = cluster.add_token_offer();
+        let present = cluster.add_token_offer();
+        let native = cluster.add_native_offer();
+        let (_, data) = cluster.offers.iter().find(|(a, _)| *a == present).unwrap();
+        let mint = OfferState::decode(data).unwrap().mint_a;
+        cluster.owners.insert(
+            associated_token_address(&cluster.treasury, &mint, &TOKEN_PROGRAM_ID),
+            TOKEN_PROGRAM_ID,
+        );
+
+        let offers = keeper(&cluster, 5).find_expired_offers().unwrap();
+        let addresses: Vec<Pubkey> = offers.iter().map(|offer| offer.address).collect();
+        assert_eq!(addresses, vec![present, native]);
+        assert!(!addresses.contains(&missing));
+
+        // Without a cancellation fee the treasury account is never read.
+        cluster.pool_data = pool_data(cluster.treasury, None);
+        assert_eq!(keeper(&cluster, 5).find_expired_offers().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn counts_closed_offers_from_event() {
+        let mut cluster = MockCluster::new();
+        cluster.add_native_offer();
+        let gone = cluster.add_native_offer();
+        cluster.add_native_offer();
+        cluster.skipped.insert(gone);
+
+        assert_eq!(keeper(&cluster, 5).run_once().unwrap(), 2);
+        assert_eq!(cluster.attempts.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,85 @@
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use log::{error, info};
+use otc_keeper::{rpc_client, Keeper, KeeperConfig};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::read_keypair_file;
+
+/// Scans an otc_pool for expired offers and closes them through `crank_expired_offers`.
+#[derive(Parser, Debug)]
+#[command(name = "otc-keeper", version)]
+struct Args {
+    /// RPC endpoint, e.g. http://127.0.0.1:8899 for solana-test-validator.
+    #[arg(
+        long,
+        env = "OTC_KEEPER_RPC_URL",
+        default_value = "http://127.0.0.1:8899"
+    )]
+    rpc_url: String,
+
+    /// Keypair that signs and pays for crank transactions and receives the bounty.
+    #[arg(long, env = "OTC_KEEPER_KEYPAIR")]
+    keypair: String,
+
+    #[arg(long, default_value = "9s97f1eHD71SCRWCFVucTdEUPwwHEcPxWV9fDqE67EME")]
+    program_id: Pubkey,
+
+    #[arg(long)]
+    pool: Pubkey,
+
+    /// Most offers closed per transaction; fewer are sent when they would not fit.
+    #[arg(long, default_value_t = 5)]
+    batch_size: usize,
+
+    #[arg(long, default_value_t = 5)]
+    max_retries: u32,
+
+    #[arg(long, default_value_t = 500)]
+    initial_backoff_ms: u64,
+
+    #[arg(long, default_value_t = 10_000)]
+    max_backoff_ms: u64,
+
+    /// Seconds to wait between scans.
+    #[arg(long, default_value_t = 30)]
+    interval_secs: u64,
+
+    /// Run a single scan and exit.
+    #[arg(long)]
+    once: bool,
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    let payer = read_keypair_file(&args.keypair)
+        .map_err(|err| anyhow!("failed to read keypair {}: {err}", args.keypair))?;
+    let keeper = Keeper::new(
+        rpc_client(args.rpc_url),
+        payer,
+        KeeperConfig {
+            program_id: args.program_id,
+            pool: args.pool,
+            batch_size: args.batch_size,
+            max_retries: args.max_retries,
+            initial_backoff: Duration::from_millis(args.initial_backoff_ms),
+            max_backoff: Duration::from_millis(args.max_backoff_ms),
+        },
+    );
+
+    loop {
+        match keeper.run_once() {
+            Ok(closed) => info!("scan complete, closed {closed} offers"),
+            Err(err) if args.once => return Err(err),
+            Err(err) => error!("scan failed: {err}"),
+        }
+        if args.once {
+            return Ok(());
+        }
+        sleep(Duration::from_secs(args.interval_secs));
+    }
+}
@@ -0,0 +1,430 @@
+//! Runs the keeper against the compiled otc_pool program in an in-process bank, so the crank
+//! account layout, the offer filters and the event decoding are checked against the real
+//! program rather than a mock. Build the program first (`anchor build`); the test loads
+//! `otc_pool.so` from `SBF_OUT_DIR`, falling back to `../target/deploy`:
+//!
+//! ```bash
+//! cargo test --test program_test -- --ignored
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use borsh::BorshSerialize;
+use otc_keeper::{
+    associated_token_address, open_offer_filters, Cluster, Keeper, KeeperConfig, SendError,
+    ASSOCIATED_TOKEN_PROGRAM_ID, NATIVE_MINT, TOKEN_PROGRAM_ID,
+};
+use solana_program_test::{BanksClient, ProgramTest, ProgramTestContext};
+use solana_sdk::account::AccountSharedData;
+use solana_sdk::clock::Clock;
+use solana_sdk::hash::{hash, Hash};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::sysvar;
+use solana_sdk::transaction::Transaction;
+use tokio::runtime::Runtime;
+
+const PROGRAM_ID: Pubkey = pubkey!("9s97f1eHD71SCRWCFVucTdEUPwwHEcPxWV9fDqE67EME");
+const MINT_LEN: u64 = 82;
+const TOKEN_ACCOUNT_AMOUNT_OFFSET: usize = 64;
+
+/// A `Cluster` backed by `BanksClient`. Banks cannot scan program accounts, so offers are
+/// registered with `track_offer` and filtered with the keeper's own `getProgramAccounts`
+/// filters.
+struct BanksCluster {
+    runtime: Runtime,
+    context: ProgramTestContext,
+    offers: RefCell<Vec<Pubkey>>,
+    logs: RefCell<HashMap<Signature, Vec<String>>>,
+}
+
+impl BanksCluster {
+    fn start() -> Self {
+        if std::env::var("SBF_OUT_DIR").is_err() && std::env::var("BPF_OUT_DIR").is_err() {
+            std::env::set_var(
+                "SBF_OUT_DIR",
+                concat!(env!("CARGO_MANIFEST_DIR"), "/../target/deploy"),
+            );
+        }
+        let runtime = Runtime::new().unwrap();
+        let context =
+            runtime.block_on(ProgramTest::new("otc_pool", PROGRAM_ID, None).start_with_context());
+        Self {
+            runtime,
+            context,
+            offers: RefCell::new(Vec::new()),
+            logs: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn client(&self) -> BanksClient {
+        self.context.banks_client.clone()
+    }
+
+    fn payer(&self) -> &Keypair {
+        &self.context.payer
+    }
+
+    fn track_offer(&self, offer: Pubkey) {
+        self.offers.borrow_mut().push(offer);
+    }
+
+    fn account(&self, key: &Pubkey) -> Option<solana_sdk::account::Account> {
+        self.runtime
+            .block_on(self.client().get_account(*key))
+            .unwrap()
+    }
+
+    fn clock(&self) -> Clock {
+        self.runtime
+            .block_on(self.client().get_sysvar::<Clock>())
+            .unwrap()
+    }
+
+    fn token_amount(&self, token_account: &Pubkey) -> u64 {
+        let data = self.account(token_account).unwrap().data;
+        u64::from_le_bytes(
+            data[TOKEN_ACCOUNT_AMOUNT_OFFSET..TOKEN_ACCOUNT_AMOUNT_OFFSET + 8]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Signs with the payer plus `signers` and requires the transaction to succeed.
+    fn process(&self, instructions: &[Instruction], signers: &[&Keypair]) {
+        let blockhash = self.latest_blockhash().unwrap();
+        let mut all_signers = vec![self.payer()];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer().pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        if let Err(err) = self.send_transaction(&transaction) {
+            panic!("transaction failed: {err}");
+        }
+    }
+}
+
+impl Cluster for &BanksCluster {
+    fn open_offers(&self, program_id: &Pubkey, pool: &Pubkey) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let filters = open_offer_filters(pool);
+        let mut offers = Vec::new();
+        for key in self.offers.borrow().iter() {
+            let Some(account) = self.account(key) else {
+                continue;
+            };
+            if account.owner != *program_id {
+                continue;
+            }
+            let shared = AccountSharedData::from(account.clone());
+            if filters.iter().all(|filter| filter.allows(&shared)) {
+                offers.push((*key, account.data));
+            }
+        }
+        Ok(offers)
+    }
+
+    fn unix_timestamp(&self) -> Result<i64> {
+        Ok(self.clock().unix_timestamp)
+    }
+
+    fn account_data(&self, key: &Pubkey) -> Result<Vec<u8>> {
+        self.account(key)
+            .map(|account| account.data)
+            .ok_or_else(|| anyhow!("account {key} not found"))
+    }
+
+    fn account_owners(&self, keys: &[Pubkey]) -> Result<Vec<Option<Pubkey>>> {
+        Ok(keys
+            .iter()
+            .map(|key| self.account(key).map(|account| account.owner))
+            .collect())
+    }
+
+    fn latest_blockhash(&self) -> Result<Hash> {
+        Ok(self
+            .runtime
+            .block_on(self.client().get_latest_blockhash())?)
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> Result<Signature, SendError> {
+        let outcome = self
+            .runtime
+            .block_on(
+                self.client()
+                    .process_transaction_with_metadata(transaction.clone()),
+            )
+            .map_err(|err| SendError::Transient(err.into()))?;
+        let signature = transaction.signatures[0];
+        let logs = outcome
+            .metadata
+            .map(|metadata| metadata.log_messages)
+            .unwrap_or_default();
+        match outcome.result {
+            Ok(()) => {
+                self.logs.borrow_mut().insert(signature, logs);
+                Ok(signature)
+            }
+            Err(err) => Err(SendError::from_transaction_error(
+                Some(err.clone()),
+                anyhow!("{err}: {logs:?}"),
+            )),
+        }
+    }
+
+    fn transaction_logs(&self, signature: &Signature) -> Result<Vec<String>> {
+        self.logs
+            .borrow()
+            .get(signature)
+            .cloned()
+            .ok_or_else(|| anyhow!("no logs for {signature}"))
+    }
+}
+
+fn program_instruction(
+    name: &str,
+    args: impl BorshSerialize,
+    accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut data = hash(format!("global:{name}").as_bytes()).to_bytes()[..8].to_vec();
+    data.extend(borsh::to_vec(&args).unwrap());
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts,
+        data,
+    }
+}
+
+fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &PROGRAM_ID).0
+}
+
+/// SPL Token `InitializeMint2` with no freeze authority.
+fn initialize_mint(mint: &Pubkey, authority: &Pubkey, decimals: u8) -> Instruction {
+    let mut data = vec![20, decimals];
+    data.extend(authority.to_bytes());
+    data.push(0);
+    Instruction {
+        program_id: TOKEN_PROGRAM_ID,
+        accounts: vec![AccountMeta::new(*mint, false)],
+        data,
+    }
+}
+
+/// Associated Token Account `CreateIdempotent`.
+fn create_associated_token_account(payer: &Pubkey, owner: &Pubkey, mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: ASSOCIATED_TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(
+                associated_token_address(owner, mint, &TOKEN_PROGRAM_ID),
+                false,
+            ),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: vec![1],
+    }
+}
+
+/// SPL Token `MintTo`.
+fn mint_to(mint: &Pubkey, destination: &Pubkey, authority: &Pubkey, amount: u64) -> Instruction {
+    let mut data = vec![7];
+    data.extend(amount.to_le_bytes());
+    Instruction {
+        program_id: TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*mint, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data,
+    }
+}
+
+#[test]
+#[ignore = "needs otc_pool.so from `anchor build`"]
+fn cranks_expired_offer_on_program() {
+    let cluster = BanksCluster::start();
+    let authority = cluster.payer().pubkey();
+    let maker = authority;
+    let treasury = Pubkey::new_unique();
+    let pool = Keypair::new();
+    let mint = Keypair::new();
+    let bounty = 1_000_000u64;
+
+    // A classic SPL mint for mint_a, with the maker holding 1_000 of it.
+    let rent = cluster
+        .runtime
+        .block_on(cluster.client().get_rent())
+        .unwrap();
+    let maker_ata = associated_token_address(&maker, &mint.pubkey(), &TOKEN_PROGRAM_ID);
+    cluster.process(
+        &[
+            system_instruction::create_account(
+                &authority,
+                &mint.pubkey(),
+                rent.minimum_balance(MINT_LEN as usize),
+                MINT_LEN,
+                &TOKEN_PROGRAM_ID,
+            ),
+            initialize_mint(&mint.pubkey(), &authority, 6),
+            create_associated_token_account(&authority, &maker, &mint.pubkey()),
+            mint_to(&mint.pubkey(), &maker_ata, &authority, 1_000),
+        ],
+        &[&mint],
+    );
+
+    // Pool trading the mint against native SOL, with the maker as a partner.
+    let pool_key = pool.pubkey();
+    let partner_record = pda(&[b"partner", pool_key.as_ref(), maker.as_ref()]);
+    let now = cluster.clock().unix_timestamp;
+    cluster.process(
+        &[
+            program_instruction(
+                "initialize_pool",
+                (
+                    10u32,
+                    0u16,
+                    treasury,
+                    1u64,
+                    86_400i64,
+                    vec![mint.pubkey(), NATIVE_MINT],
+                ),
+                vec![
+                    AccountMeta::new(pool_key, true),
+                    AccountMeta::new(authority, true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                ],
+            ),
+            program_instruction(
+                "add_supported_pair",
+                (mint.pubkey(), NATIVE_MINT),
+                vec![
+                    AccountMeta::new(pool_key, false),
+                    AccountMeta::new_readonly(authority, true),
+                ],
+            ),
+            program_instruction(
+                "set_crank_bounty",
+                bounty,
+                vec![
+                    AccountMeta::new(pool_key, false),
+                    AccountMeta::new_readonly(authority, true),
+                ],
+            ),
+            program_instruction(
+                "add_partner",
+                (maker, 0u8, now + 365 * 86_400, [0u8; 32]),
+                vec![
+                    AccountMeta::new(pool_key, false),
+                    AccountMeta::new(authority, true),
+                    AccountMeta::new(partner_record, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            ),
+        ],
+        &[&pool],
+    );
+
+    // An offer of 500 of the mint for 0.001 SOL, expiring in two minutes.
+    let offer_id = 1u64;
+    let offer = pda(&[b"offer", maker.as_ref(), &offer_id.to_le_bytes()]);
+    let escrow = pda(&[b"escrow", offer.as_ref()]);
+    let expiration_ts = now + 120;
+    cluster.process(
+        &[program_instruction(
+            "create_offer",
+            (
+                offer_id,
+                500u64,
+                1_000_000u64,
+                expiration_ts,
+                Vec::<Pubkey>::new(),
+                (false, 0u64, 0u16),
+            ),
+            vec![
+                AccountMeta::new(pool_key, false),
+                AccountMeta::new(maker, true),
+                AccountMeta::new_readonly(partner_record, false),
+                AccountMeta::new(
+                    pda(&[
+                        b"partner_limit",
+                        pool_key.as_ref(),
+                        maker.as_ref(),
+                        mint.pubkey().as_ref(),
+                    ]),
+                    false,
+                ),
+                AccountMeta::new(offer, false),
+                AccountMeta::new(maker_ata, false),
+                AccountMeta::new(escrow, false),
+                AccountMeta::new_readonly(mint.pubkey(), false),
+                // mint_b is native SOL, so the optional account is omitted.
+                AccountMeta::new_readonly(PROGRAM_ID, false),
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ],
+        )],
+        &[],
+    );
+    cluster.track_offer(offer);
+    assert_eq!(cluster.token_amount(&maker_ata), 500);
+
+    let keeper_payer = Keypair::new();
+    cluster.process(
+        &[system_instruction::transfer(
+            &authority,
+            &keeper_payer.pubkey(),
+            1_000_000_000,
+        )],
+        &[],
+    );
+    let keeper_key = keeper_payer.pubkey();
+    let keeper = Keeper::new(
+        &cluster,
+        keeper_payer,
+        KeeperConfig {
+            program_id: PROGRAM_ID,
+            pool: pool_key,
+            batch_size: 5,
+            max_retries: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        },
+    );
+    assert!(keeper.find_expired_offers().unwrap().is_empty());
+
+    let mut clock = cluster.clock();
+    clock.unix_timestamp = expiration_ts + 1;
+    cluster.context.set_sysvar(&clock);
+
+    let expired = keeper.find_expired_offers().unwrap();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].address, offer);
+    assert_eq!(expired[0].escrow, escrow);
+
+    let keeper_balance = cluster.account(&keeper_key).unwrap().lamports;
+    assert_eq!(keeper.run_once().unwrap(), 1);
+
+    assert!(cluster.account(&offer).is_none());
+    assert!(cluster.account(&escrow).is_none());
+    assert_eq!(cluster.token_amount(&maker_ata), 1_000);
+    // The keeper earns the maker's crank deposit, less the transaction fee.
+    assert!(cluster.account(&keeper_key).unwrap().lamports > keeper_balance + bounty / 2);
+    assert!(keeper.find_expired_offers().unwrap().is_empty());
+}
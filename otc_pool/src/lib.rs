@@ -1,11 +1,46 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
-use solana_program::{program::invoke, system_instruction};
+use anchor_lang::Discriminator;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{instruction::harvest_withheld_tokens_to_mint, TransferFeeConfig},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+};
+use anchor_spl::token_interface::{self, CloseAccount, Mint, TokenAccount, TokenInterface};
+use solana_program::{
+    instruction::Instruction,
+    program::{invoke, invoke_signed},
+    system_instruction,
+};
 
 
 declare_id!("9s97f1eHD71SCRWCFVucTdEUPwwHEcPxWV9fDqE67EME");
 
 pub const NATIVE_MINT: Pubkey = Pubkey::new_from_array([0u8; 32]);
+pub const NATIVE_DECIMALS: u8 = 9;
+/// Thirty days.
+pub const DEFAULT_TIER_WINDOW_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Accounts passed per offer to `crank_expired_offers`:
+/// offer, escrow, maker, maker token account, mint_a, treasury destination, maker's
+/// `PartnerLimit` for mint_a. The escrow, token account and mint slots are ignored for native
+/// SOL offers, whose treasury destination is the treasury wallet. The treasury slot is only
+/// read when the pool charges a cancellation fee.
+pub const CRANK_ACCOUNTS_PER_OFFER: usize = 7;
+/// Shortest time an offer may be open before it expires.
+pub const MIN_OFFER_LIFETIME_SECS: i64 = 60;
+/// Hourly buckets backing the rolling 24h volume in `PartnerLimit`.
+pub const LIMIT_WINDOW_HOURS: usize = 24;
+
+/// Bits of `Pool::paused_operations`. `Pool::paused` still halts every operation.
+pub const PAUSE_CREATE: u8 = 1 << 0;
+pub const PAUSE_ACCEPT: u8 = 1 << 1;
+pub const PAUSE_SWAP: u8 = 1 << 2;
+/// Also covers closing expired offers.
+pub const PAUSE_CANCEL: u8 = 1 << 3;
+pub const PAUSE_EXTEND: u8 = 1 << 4;
+pub const PAUSE_ALL: u8 = PAUSE_CREATE | PAUSE_ACCEPT | PAUSE_SWAP | PAUSE_CANCEL | PAUSE_EXTEND;
 
 #[program]
 pub mod otc_pool {
@@ -13,7 +48,7 @@ pub mod otc_pool {
 
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
-        max_partners: u8,
+        max_partners: u32,
         fee_bps: u16,
         treasury: Pubkey,
         min_swap_amount: u64,
@@ -22,8 +57,26 @@ pub mod otc_pool {
     ) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         pool.authority = *ctx.accounts.authority.key;
+        pool.legacy_max_partners = 0;
+        pool.legacy_partners = Vec::new();
         pool.max_partners = max_partners;
-        pool.partners = Vec::new();
+        pool.partner_count = 0;
+        pool.pending_authority = None;
+        pool.pending_treasury = None;
+        pool.role_grants = Vec::new();
+        pool.timelock_delay_secs = 0;
+        pool.next_change_id = 0;
+        pool.paused_operations = 0;
+        pool.frozen_pairs = Vec::new();
+        pool.guardian = None;
+        pool.emergency_pause_secs = 0;
+        pool.emergency_paused_until = 0;
+        pool.extension_policy = ExtensionPolicy {
+            max_extensions: 1,
+            max_offer_lifetime_secs: 0,
+        };
+        pool.guardian_pause_used = false;
+        pool.config_version = Pool::CONFIG_VERSION;
         pool.whitelisted_mints = initial_whitelisted_mints;
         pool.supported_pairs = Vec::new();
         pool.paused = false;
@@ -31,6 +84,16 @@ pub mod otc_pool {
         pool.treasury = treasury;
         pool.min_swap_amount = min_swap_amount;
         pool.max_expiration_secs = max_expiration_secs;
+        pool.crank_bounty_lamports = 0;
+        pool.swap_fee_policy = SwapFeePolicy::SideA;
+        pool.cancellation_fee_policy = CancellationFeePolicy::None;
+        pool.offer_fees = OfferFeeSchedule {
+            maker_fee_bps: 0,
+            taker_fee_bps: fee_bps,
+            taker_fee_currency: FeeCurrency::MintA,
+        };
+        pool.fee_tiers = Vec::new();
+        pool.tier_window_secs = DEFAULT_TIER_WINDOW_SECS;
         emit!(PoolInitialized {
             authority: pool.authority,
             max_partners,
@@ -42,27 +105,233 @@ pub mod otc_pool {
         Ok(())
     }
 
-    pub fn transfer_authority(ctx: Context<TransferAuthority>, new_authority: Pubkey) -> Result<()> {
+    /// First step of an authority transfer. Nothing changes until `new_authority` signs
+    /// `accept_authority`, so a mistyped key cannot take over the pool.
+    pub fn propose_authority(ctx: Context<TransferAuthority>, new_authority: Pubkey) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        pool.pending_authority = Some(new_authority);
+        emit!(AuthorityProposed { current: pool.authority, proposed: new_authority });
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let new_authority = pool.pending_authority.ok_or(OtcError::NoPendingProposal)?;
+        require_keys_eq!(new_authority, ctx.accounts.new_authority.key(), OtcError::Unauthorized);
         let previous = pool.authority;
         pool.authority = new_authority;
+        pool.pending_authority = None;
         emit!(AuthorityTransferred { previous, new: new_authority });
         Ok(())
     }
 
-    pub fn update_treasury(ctx: Context<TransferAuthority>, new_treasury: Pubkey) -> Result<()> {
+    pub fn cancel_authority_proposal(ctx: Context<TransferAuthority>) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        let cancelled = pool.pending_authority.take().ok_or(OtcError::NoPendingProposal)?;
+        emit!(AuthorityProposalCancelled { cancelled });
+        Ok(())
+    }
+
+    /// First step of a treasury change; the new treasury wallet confirms with `accept_treasury`.
+    /// While the pool has a timelock delay, queue `ParamChange::Treasury` instead.
+    pub fn propose_treasury(ctx: Context<ModifyPoolState>, new_treasury: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        pool.apply_immediately(ParamChange::Treasury(new_treasury))
+    }
+
+    pub fn accept_treasury(ctx: Context<AcceptTreasury>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let new_treasury = pool.pending_treasury.ok_or(OtcError::NoPendingProposal)?;
+        require_keys_eq!(new_treasury, ctx.accounts.new_treasury.key(), OtcError::Unauthorized);
         let previous = pool.treasury;
         pool.treasury = new_treasury;
+        pool.pending_treasury = None;
         emit!(TreasuryUpdated { previous, new: new_treasury });
         Ok(())
     }
 
-    pub fn add_whitelisted_mint(ctx: Context<ModifyPoolMints>, mint: Pubkey) -> Result<()> {
+    pub fn cancel_treasury_proposal(ctx: Context<ModifyPoolState>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        let cancelled = pool.pending_treasury.take().ok_or(OtcError::NoPendingProposal)?;
+        emit!(TreasuryProposalCancelled { cancelled });
+        Ok(())
+    }
+
+    /// Grants `role` to `member`. Only the pool authority, which implicitly holds every role,
+    /// manages the registry.
+    pub fn grant_role(ctx: Context<TransferAuthority>, member: Pubkey, role: Role) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        require!(!pool.has_role(&member, role), OtcError::RoleAlreadyGranted);
+        require!(pool.role_grants.len() < Pool::MAX_ROLE_GRANTS, OtcError::RoleRegistryFull);
+        pool.role_grants.push(RoleGrant { member, role });
+        emit!(RoleGranted { member, role });
+        Ok(())
+    }
+
+    pub fn revoke_role(ctx: Context<TransferAuthority>, member: Pubkey, role: Role) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        let i = pool
+            .role_grants
+            .iter()
+            .position(|g| g.member == member && g.role == role)
+            .ok_or(OtcError::RoleNotGranted)?;
+        pool.role_grants.swap_remove(i);
+        emit!(RoleRevoked { member, role });
+        Ok(())
+    }
+
+    /// Creates the pool's M-of-N admin council. The council acts through its signer PDA
+    /// (`[b"council_signer", council]`): propose that PDA as the pool authority, then execute a
+    /// council proposal calling `accept_authority` to hand control over.
+    pub fn init_admin_council(
+        ctx: Context<InitAdminCouncil>,
+        members: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        require_keys_eq!(ctx.accounts.pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        AdminCouncil::validate_members(&members, threshold)?;
+        let council = &mut ctx.accounts.council;
+        council.pool = ctx.accounts.pool.key();
+        council.members = members.clone();
+        council.threshold = threshold;
+        council.proposal_count = 0;
+        council.bump = ctx.bumps.council;
+        council.signer_bump = ctx.bumps.council_signer;
+        emit!(AdminCouncilInitialized {
+            council: council.key(),
+            signer: ctx.accounts.council_signer.key(),
+            members,
+            threshold,
+        });
+        Ok(())
+    }
+
+    /// Replaces the member set and threshold. Only callable by the council itself, i.e. from
+    /// an executed council proposal.
+    pub fn update_admin_council(
+        ctx: Context<UpdateAdminCouncil>,
+        members: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        AdminCouncil::validate_members(&members, threshold)?;
+        let council = &mut ctx.accounts.council;
+        council.members = members.clone();
+        council.threshold = threshold;
+        emit!(AdminCouncilUpdated { council: council.key(), members, threshold });
+        Ok(())
+    }
+
+    /// Proposes one call into this program, signed by the council signer PDA on execution.
+    /// The proposer's approval is recorded with the proposal.
+    pub fn create_council_proposal(
+        ctx: Context<CreateCouncilProposal>,
+        accounts: Vec<ProposalAccount>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let council = &mut ctx.accounts.council;
+        let proposer = ctx.accounts.proposer.key();
+        require!(council.members.contains(&proposer), OtcError::NotCouncilMember);
+        require!(
+            accounts.len() <= CouncilProposal::MAX_ACCOUNTS && data.len() <= CouncilProposal::MAX_DATA_LEN,
+            OtcError::ProposalTooLarge
+        );
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.council = council.key();
+        proposal.id = council.proposal_count;
+        proposal.proposer = proposer;
+        proposal.accounts = accounts;
+        proposal.data = data;
+        proposal.approvals = vec![proposer];
+        proposal.executed = false;
+        proposal.bump = ctx.bumps.proposal;
+        council.proposal_count = council.proposal_count.checked_add(1).ok_or(OtcError::MathOverflow)?;
+
+        emit!(CouncilProposalCreated {
+            council: council.key(),
+            proposal: proposal.key(),
+            id: proposal.id,
+            proposer,
+        });
+        Ok(())
+    }
+
+    pub fn approve_council_proposal(ctx: Context<ApproveCouncilProposal>) -> Result<()> {
+        let member = ctx.accounts.member.key();
+        require!(ctx.accounts.council.members.contains(&member), OtcError::NotCouncilMember);
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, OtcError::ProposalAlreadyExecuted);
+        require!(!proposal.approvals.contains(&member), OtcError::AlreadyApproved);
+        proposal.approvals.push(member);
+        emit!(CouncilProposalApproved {
+            proposal: proposal.key(),
+            member,
+            approvals: ctx.accounts.council.approval_count(&proposal.approvals) as u8,
+        });
+        Ok(())
+    }
+
+    /// Executes a proposal once approvals from current members reach the threshold.
+    /// `remaining_accounts` are the proposal's accounts in order, followed by this program.
+    pub fn execute_council_proposal<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteCouncilProposal<'info>>,
+    ) -> Result<()> {
+        let council = &ctx.accounts.council;
+        require!(
+            council.members.contains(&ctx.accounts.executor.key()),
+            OtcError::NotCouncilMember
+        );
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, OtcError::ProposalAlreadyExecuted);
+        require!(
+            council.approval_count(&proposal.approvals) >= council.threshold as usize,
+            OtcError::ThresholdNotMet
+        );
+        require!(
+            ctx.remaining_accounts.len() >= proposal.accounts.len(),
+            OtcError::ProposalAccountMismatch
+        );
+        for (account, info) in proposal.accounts.iter().zip(ctx.remaining_accounts) {
+            require_keys_eq!(account.pubkey, info.key(), OtcError::ProposalAccountMismatch);
+        }
+
+        // Persist the executed flag before the CPI so the proposal cannot re-enter itself.
+        proposal.executed = true;
+        proposal.exit(&crate::ID)?;
+
+        let ix = Instruction {
+            program_id: crate::ID,
+            accounts: proposal
+                .accounts
+                .iter()
+                .map(|a| AccountMeta {
+                    pubkey: a.pubkey,
+                    is_signer: a.is_signer,
+                    is_writable: a.is_writable,
+                })
+                .collect(),
+            data: proposal.data.clone(),
+        };
+        let council_key = council.key();
+        let seeds = &[b"council_signer", council_key.as_ref(), &[council.signer_bump]];
+        invoke_signed(&ix, ctx.remaining_accounts, &[seeds])?;
+
+        emit!(CouncilProposalExecuted {
+            proposal: proposal.key(),
+            id: proposal.id,
+        });
+        Ok(())
+    }
+
+    pub fn add_whitelisted_mint(ctx: Context<ModifyPoolMints>, mint: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::ListingManager)?;
         require!(!pool.whitelisted_mints.contains(&mint), OtcError::MintAlreadyWhitelisted);
         pool.whitelisted_mints.push(mint);
         emit!(MintWhitelisted { mint });
@@ -71,7 +340,7 @@ pub mod otc_pool {
 
     pub fn remove_whitelisted_mint(ctx: Context<ModifyPoolMints>, mint: Pubkey) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        pool.require_role(&ctx.accounts.authority.key(), Role::ListingManager)?;
         if let Some(i) = pool.whitelisted_mints.iter().position(|x| *x == mint) {
             pool.whitelisted_mints.swap_remove(i);
             emit!(MintRemoved { mint });
@@ -81,39 +350,611 @@ pub mod otc_pool {
         }
     }
 
-    pub fn close_expired_offer(ctx: Context<CloseExpiredOffer>) -> Result<()> {
-        let offer = &mut ctx.accounts.offer;
+    pub fn close_expired_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, CloseExpiredOffer<'info>>,
+    ) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        ctx.accounts.pool.ensure_not_paused(PAUSE_CANCEL)?;
         require!(!offer.fulfilled, OtcError::OfferAlreadyFulfilled);
         let clock = &ctx.accounts.clock;
         require!(clock.unix_timestamp > offer.expiration_ts, OtcError::OfferNotExpired);
-        offer.fulfilled = true;
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let leg_a = Leg {
+            mint: offer.mint_a,
+            mint_account: ctx.accounts.mint_a.as_ref(),
+            token_program: &token_program,
+            hook_accounts: ctx.remaining_accounts,
+        };
+        let maker_token_dest = ctx.accounts.maker_token_dest.as_ref().map(|a| a.to_account_info());
+        let treasury_dest = if offer.mint_a == NATIVE_MINT {
+            ctx.accounts.treasury.clone()
+        } else {
+            ctx.accounts.treasury_token_account.as_ref().map(|a| a.to_account_info())
+        };
+        let (refunded_amount_a, cancellation_fee) = refund_and_close_escrow(
+            &leg_a,
+            offer,
+            ctx.accounts.escrow_account.as_ref(),
+            &ctx.accounts.maker,
+            maker_token_dest.as_ref(),
+            treasury_dest.as_ref(),
+            &ctx.accounts.pool.cancellation_fee_policy,
+            clock.unix_timestamp,
+        )?;
+        update_partner_limit(&ctx.accounts.maker_limit, |limit| {
+            limit.release_open_escrow(offer.amount_a);
+            Ok(())
+        })?;
+
+        // The offer account itself is closed to the maker by the `close` constraint.
         emit!(OfferExpired {
             maker: offer.maker,
+            offer_id: offer.offer_id,
             expiration_ts: offer.expiration_ts,
+            refunded_amount_a,
+            cancellation_fee,
+        });
+        Ok(())
+    }
+
+    /// Permissionless batch version of `close_expired_offer`. The first `offer_count` groups of
+    /// `CRANK_ACCOUNTS_PER_OFFER` remaining accounts describe offers; anything after them is
+    /// forwarded to transfer hooks. All token offers in a batch must use `token_program`.
+    /// Entries that are already fulfilled or not yet expired are skipped so a racing fill does
+    /// not fail the batch.
+    pub fn crank_expired_offers<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CrankExpiredOffers<'info>>,
+        offer_count: u8,
+    ) -> Result<()> {
+        let groups_len = offer_count as usize * CRANK_ACCOUNTS_PER_OFFER;
+        require!(
+            offer_count > 0 && ctx.remaining_accounts.len() >= groups_len,
+            OtcError::InvalidCrankAccounts
+        );
+        ctx.accounts.pool.ensure_not_paused(PAUSE_CANCEL)?;
+        let (groups, hook_accounts) = ctx.remaining_accounts.split_at(groups_len);
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let pool_key = ctx.accounts.pool.key();
+        let treasury = ctx.accounts.pool.treasury;
+        let fee_policy = ctx.accounts.pool.cancellation_fee_policy;
+        let now = ctx.accounts.clock.unix_timestamp;
+        let mut closed: u64 = 0;
+        let mut bounty_paid: u64 = 0;
+
+        for accounts in groups.chunks(CRANK_ACCOUNTS_PER_OFFER) {
+            let [offer_info, escrow_info, maker_info, maker_token_dest_info, mint_info, treasury_info, limit_info] =
+                accounts
+            else {
+                return err!(OtcError::InvalidCrankAccounts);
+            };
+            let mut offer = Account::<Offer>::try_from(offer_info)?;
+            if offer.fulfilled || now <= offer.expiration_ts {
+                continue;
+            }
+            require_keys_eq!(offer.pool, pool_key, OtcError::InvalidCrankAccounts);
+            require_keys_eq!(offer.maker, maker_info.key(), OtcError::InvalidCrankAccounts);
+
+            // Native offers have no escrow token account or mint; their slots are ignored.
+            let (escrow_account, mint_a) = if offer.mint_a == NATIVE_MINT {
+                (None, None)
+            } else {
+                let escrow_key = Pubkey::create_program_address(
+                    &[b"escrow", offer_info.key.as_ref(), &[offer.escrow_bump]],
+                    ctx.program_id,
+                )
+                .map_err(|_| error!(OtcError::InvalidCrankAccounts))?;
+                require_keys_eq!(escrow_key, escrow_info.key(), OtcError::InvalidCrankAccounts);
+                require_keys_eq!(mint_info.key(), offer.mint_a, OtcError::InvalidCrankAccounts);
+                require_keys_eq!(*mint_info.owner, token_program.key(), OtcError::InvalidCrankAccounts);
+
+                let maker_token_dest =
+                    InterfaceAccount::<TokenAccount>::try_from(maker_token_dest_info)?;
+                require!(
+                    maker_token_dest.owner == offer.maker && maker_token_dest.mint == offer.mint_a,
+                    OtcError::InvalidCrankAccounts
+                );
+                (
+                    Some(InterfaceAccount::<TokenAccount>::try_from(escrow_info)?),
+                    Some(InterfaceAccount::<Mint>::try_from(mint_info)?),
+                )
+            };
+
+            let treasury_dest = if fee_policy == CancellationFeePolicy::None {
+                None
+            } else if offer.mint_a == NATIVE_MINT {
+                require_keys_eq!(treasury_info.key(), treasury, OtcError::InvalidTreasuryAccount);
+                Some(treasury_info)
+            } else {
+                let treasury_token_account = InterfaceAccount::<TokenAccount>::try_from(treasury_info)?;
+                require!(
+                    treasury_token_account.owner == treasury && treasury_token_account.mint == offer.mint_a,
+                    OtcError::InvalidTreasuryAccount
+                );
+                Some(treasury_info)
+            };
+
+            let leg_a = Leg {
+                mint: offer.mint_a,
+                mint_account: mint_a.as_ref(),
+                token_program: &token_program,
+                hook_accounts,
+            };
+            let (refunded_amount_a, cancellation_fee) = refund_and_close_escrow(
+                &leg_a,
+                &offer,
+                escrow_account.as_ref(),
+                maker_info,
+                Some(maker_token_dest_info),
+                treasury_dest,
+                &fee_policy,
+                now,
+            )?;
+            // The keeper earns the maker's crank deposit; the maker gets the rest back.
+            bounty_paid += release_crank_deposit(&mut offer, &ctx.accounts.keeper)?;
+            offer.close(maker_info.clone())?;
+
+            let (limit_key, _) = Pubkey::find_program_address(
+                &[b"partner_limit", pool_key.as_ref(), offer.maker.as_ref(), offer.mint_a.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(limit_key, limit_info.key(), OtcError::InvalidCrankAccounts);
+            update_partner_limit(limit_info, |limit| {
+                limit.release_open_escrow(offer.amount_a);
+                Ok(())
+            })?;
+
+            emit!(OfferExpired {
+                maker: offer.maker,
+                offer_id: offer.offer_id,
+                expiration_ts: offer.expiration_ts,
+                refunded_amount_a,
+                cancellation_fee,
+            });
+            closed += 1;
+        }
+
+        emit!(ExpiredOffersCranked {
+            keeper: ctx.accounts.keeper.key(),
+            closed,
+            bounty_paid,
         });
         Ok(())
     }
 
-    pub fn add_partner(ctx: Context<ModifyPartner>, partner: Pubkey) -> Result<()> {
+    /// Updates any subset of the pool's core settings. `fee_bps`, `min_swap_amount` and
+    /// `max_expiration_secs` are timelocked, so they are rejected here while the pool has a
+    /// timelock delay and must be queued instead. `fee_bps` only applies to `swap_direct`;
+    /// offer fills charge the pool's `offer_fees`, changed with `set_offer_fees`. A call that
+    /// only changes `fee_bps` may also come from a `FeeAdmin`. At least one field must be set.
+    pub fn update_pool_config(
+        ctx: Context<ModifyPoolState>,
+        fee_bps: Option<u16>,
+        min_swap_amount: Option<u64>,
+        max_expiration_secs: Option<i64>,
+        max_partners: Option<u32>,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let signer = ctx.accounts.authority.key();
+        let fee_only = min_swap_amount.is_none() && max_expiration_secs.is_none() && max_partners.is_none();
+        require!(fee_bps.is_some() || !fee_only, OtcError::InvalidPoolConfig);
+        match fee_bps {
+            Some(bps) if fee_only => pool.require_change_role(&signer, &ParamChange::FeeBps(bps))?,
+            _ => require_keys_eq!(pool.authority, signer, OtcError::Unauthorized),
+        }
+        let timelocked = fee_bps.is_some() || min_swap_amount.is_some() || max_expiration_secs.is_some();
+        require!(!timelocked || pool.timelock_delay_secs == 0, OtcError::TimelockActive);
+
+        let previous = pool.config();
+        if let Some(bps) = fee_bps {
+            ParamChange::FeeBps(bps).validate()?;
+            pool.fee_bps = bps;
+        }
+        if let Some(amount) = min_swap_amount {
+            pool.min_swap_amount = amount;
+        }
+        if let Some(secs) = max_expiration_secs {
+            ParamChange::MaxExpirationSecs(secs).validate()?;
+            pool.max_expiration_secs = secs;
+        }
+        if let Some(max) = max_partners {
+            require!(max >= pool.partner_count, OtcError::InvalidPoolConfig);
+            pool.max_partners = max;
+        }
+        emit!(PoolConfigUpdated { previous, new: pool.config() });
+        Ok(())
+    }
+
+    pub fn set_extension_policy(ctx: Context<ModifyPoolState>, policy: ExtensionPolicy) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
-        require!((pool.partners.len() as u8) < pool.max_partners, OtcError::PartnerLimitReached);
-        require!(!pool.partners.contains(&partner), OtcError::PartnerAlreadyExists);
-        pool.partners.push(partner);
-        emit!(PartnerAdded { partner });
+        require!(policy.max_offer_lifetime_secs >= 0, OtcError::InvalidPoolConfig);
+        let previous = pool.extension_policy;
+        pool.extension_policy = policy;
+        emit!(ExtensionPolicyUpdated { previous, new: policy });
+        Ok(())
+    }
+
+    /// Sets the crank deposit `create_offer` takes from makers. It applies to offers created
+    /// afterwards; each offer keeps the deposit it was created with.
+    pub fn set_crank_bounty(ctx: Context<ModifyPoolState>, bounty_lamports: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        let previous = pool.crank_bounty_lamports;
+        pool.crank_bounty_lamports = bounty_lamports;
+        emit!(CrankBountyUpdated { previous, new: bounty_lamports });
         Ok(())
     }
 
-    pub fn remove_partner(ctx: Context<ModifyPartner>, partner: Pubkey) -> Result<()> {
+    pub fn set_swap_fee_policy(ctx: Context<ModifyPoolState>, policy: SwapFeePolicy) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        pool.apply_immediately(ParamChange::SwapFeePolicy(policy))
+    }
+
+    pub fn set_cancellation_fee_policy(
+        ctx: Context<ModifyPoolState>,
+        policy: CancellationFeePolicy,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        pool.apply_immediately(ParamChange::CancellationFeePolicy(policy))
+    }
+
+    pub fn set_offer_fees(ctx: Context<ModifyPoolState>, fees: OfferFeeSchedule) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        pool.apply_immediately(ParamChange::OfferFees(fees))
+    }
+
+    /// Replaces the fee tier table. Partners keep their current tier index until their next
+    /// `recompute_partner_tier`, so reordering the table takes effect at window boundaries.
+    pub fn set_fee_tiers(
+        ctx: Context<ModifyPoolState>,
+        tiers: Vec<FeeTier>,
+        tier_window_secs: i64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        pool.apply_immediately(ParamChange::FeeTiers { tiers, tier_window_secs })
+    }
+
+    /// Raising the delay takes effect at once; lowering it has to be queued like any other
+    /// timelocked change so it cannot be used to skip the delay.
+    pub fn set_timelock_delay(ctx: Context<ModifyPoolState>, delay_secs: i64) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
-        if let Some(i) = pool.partners.iter().position(|x| *x == partner) {
-            pool.partners.swap_remove(i);
-            emit!(PartnerRemoved { partner });
-            Ok(())
-        } else {
-            err!(OtcError::PartnerNotFound)
+        require!(delay_secs >= pool.timelock_delay_secs, OtcError::TimelockActive);
+        let change = ParamChange::TimelockDelay(delay_secs);
+        change.validate()?;
+        change.apply(pool);
+        Ok(())
+    }
+
+    /// Schedules a fee, treasury or limit change for `eta`, which must be at least the pool's
+    /// timelock delay away. Anyone can execute it once `eta` has passed.
+    pub fn queue_change(ctx: Context<QueueChange>, change: ParamChange, eta: i64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_change_role(&ctx.accounts.proposer.key(), &change)?;
+        change.validate()?;
+        let now = Clock::get()?.unix_timestamp;
+        let earliest = now.checked_add(pool.timelock_delay_secs).ok_or(OtcError::MathOverflow)?;
+        require!(eta >= earliest, OtcError::EtaTooEarly);
+
+        let queued = &mut ctx.accounts.queued_change;
+        queued.pool = pool.key();
+        queued.id = pool.next_change_id;
+        queued.proposer = ctx.accounts.proposer.key();
+        queued.change = change.clone();
+        queued.eta = eta;
+        queued.bump = ctx.bumps.queued_change;
+        pool.next_change_id = pool.next_change_id.checked_add(1).ok_or(OtcError::MathOverflow)?;
+
+        emit!(ChangeQueued {
+            id: queued.id,
+            proposer: queued.proposer,
+            change,
+            eta,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_change(ctx: Context<CancelChange>) -> Result<()> {
+        let queued = &ctx.accounts.queued_change;
+        ctx.accounts.pool.require_change_role(&ctx.accounts.authority.key(), &queued.change)?;
+        emit!(ChangeCancelled {
+            id: queued.id,
+            change: queued.change.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn execute_change(ctx: Context<ExecuteChange>) -> Result<()> {
+        let queued = &ctx.accounts.queued_change;
+        require!(Clock::get()?.unix_timestamp >= queued.eta, OtcError::TimelockNotElapsed);
+        queued.change.clone().apply(&mut ctx.accounts.pool);
+        emit!(ChangeExecuted {
+            id: queued.id,
+            change: queued.change.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn init_partner_stats(ctx: Context<InitPartnerStats>, partner: Pubkey) -> Result<()> {
+        let stats = &mut ctx.accounts.partner_stats;
+        stats.pool = ctx.accounts.pool.key();
+        stats.partner = partner;
+        stats.tier = 0;
+        stats.window_start_ts = Clock::get()?.unix_timestamp;
+        stats.volumes = Vec::new();
+        stats.bump = ctx.bumps.partner_stats;
+        emit!(PartnerStatsInitialized { partner });
+        Ok(())
+    }
+
+    /// Permissionless. Once the pool's tier window has elapsed, assigns the partner the highest
+    /// tier whose threshold their volume for one window meets, then starts a new window.
+    pub fn recompute_partner_tier(ctx: Context<RecomputePartnerTier>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let stats = &mut ctx.accounts.partner_stats;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= stats.window_start_ts.saturating_add(pool.tier_window_secs),
+            OtcError::TierWindowNotElapsed
+        );
+
+        let previous_tier = stats.tier;
+        stats.tier = pool
+            .fee_tiers
+            .iter()
+            .rposition(|tier| {
+                stats.volume_per_window(&tier.mint, now, pool.tier_window_secs) >= tier.min_volume
+            })
+            .map_or(0, |i| i as u8 + 1);
+        for volume in stats.volumes.iter_mut() {
+            volume.window_volume = 0;
+        }
+        stats.window_start_ts = now;
+
+        emit!(PartnerTierUpdated {
+            partner: stats.partner,
+            previous_tier,
+            new_tier: stats.tier,
+        });
+        Ok(())
+    }
+
+    pub fn init_rebate_vault(ctx: Context<InitRebateVault>) -> Result<()> {
+        ctx.accounts.pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        emit!(RebateVaultInitialized {
+            mint: ctx.accounts.mint.key(),
+            vault: ctx.accounts.rebate_vault.key(),
+        });
+        Ok(())
+    }
+
+    pub fn init_native_rebate_vault(ctx: Context<InitNativeRebateVault>) -> Result<()> {
+        ctx.accounts.pool.require_role(&ctx.accounts.authority.key(), Role::FeeAdmin)?;
+        let vault = &mut ctx.accounts.rebate_vault;
+        vault.pool = ctx.accounts.pool.key();
+        vault.bump = ctx.bumps.rebate_vault;
+        emit!(RebateVaultInitialized {
+            mint: NATIVE_MINT,
+            vault: vault.key(),
+        });
+        Ok(())
+    }
+
+    pub fn add_partner(
+        ctx: Context<AddPartner>,
+        partner: Pubkey,
+        kyc_tier: u8,
+        expiry_ts: i64,
+        reference_hash: [u8; 32],
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::PartnerManager)?;
+        require!(pool.partner_count < pool.max_partners, OtcError::PartnerLimitReached);
+        // Legacy partners get their record through `migrate_partner`, which also counts them.
+        require!(!pool.legacy_partners.contains(&partner), OtcError::PartnerAlreadyExists);
+        let now = Clock::get()?.unix_timestamp;
+        require!(expiry_ts > now, OtcError::InvalidPartnerExpiry);
+        pool.partner_count += 1;
+
+        let record = &mut ctx.accounts.partner_record;
+        record.pool = pool.key();
+        record.partner = partner;
+        record.bump = ctx.bumps.partner_record;
+        record.kyc_tier = kyc_tier;
+        record.onboarded_ts = now;
+        record.expiry_ts = expiry_ts;
+        record.status = PartnerStatus::Active;
+        record.reference_hash = reference_hash;
+        record.frozen = false;
+        emit!(PartnerAdded {
+            partner,
+            kyc_tier,
+            onboarded_ts: now,
+            expiry_ts,
+            reference_hash,
+        });
+        Ok(())
+    }
+
+    pub fn update_partner_metadata(
+        ctx: Context<ModifyPartnerRecord>,
+        kyc_tier: u8,
+        expiry_ts: i64,
+        reference_hash: [u8; 32],
+    ) -> Result<()> {
+        ctx.accounts.pool.require_role(&ctx.accounts.authority.key(), Role::PartnerManager)?;
+        let record = &mut ctx.accounts.partner_record;
+        record.kyc_tier = kyc_tier;
+        record.expiry_ts = expiry_ts;
+        record.reference_hash = reference_hash;
+        emit!(PartnerMetadataUpdated {
+            partner: record.partner,
+            kyc_tier,
+            expiry_ts,
+            reference_hash,
+        });
+        Ok(())
+    }
+
+    /// Suspends or reinstates a partner, or offboards them. Offboarding is final; the record
+    /// has to be removed and the partner added again.
+    pub fn set_partner_status(ctx: Context<ModifyPartnerRecord>, status: PartnerStatus) -> Result<()> {
+        ctx.accounts.pool.require_role(&ctx.accounts.authority.key(), Role::PartnerManager)?;
+        let record = &mut ctx.accounts.partner_record;
+        let previous = record.status;
+        require!(
+            previous != PartnerStatus::Offboarded && previous != status,
+            OtcError::InvalidStatusTransition
+        );
+        record.status = status;
+        emit!(PartnerStatusChanged {
+            partner: record.partner,
+            previous,
+            new: status,
+        });
+        Ok(())
+    }
+
+    pub fn init_partner_limit(
+        ctx: Context<InitPartnerLimit>,
+        partner: Pubkey,
+        mint: Pubkey,
+        limits: MintLimits,
+    ) -> Result<()> {
+        ctx.accounts.pool.require_role(&ctx.accounts.authority.key(), Role::PartnerManager)?;
+        let limit = &mut ctx.accounts.partner_limit;
+        limit.pool = ctx.accounts.pool.key();
+        limit.partner = partner;
+        limit.mint = mint;
+        limit.limits = limits;
+        limit.last_hour = Clock::get()?.unix_timestamp / 3600;
+        limit.hourly_volume = [0; LIMIT_WINDOW_HOURS];
+        limit.open_escrow = 0;
+        limit.bump = ctx.bumps.partner_limit;
+        emit!(PartnerLimitSet { partner, mint, previous: None, new: limits });
+        Ok(())
+    }
+
+    /// Sets or raises a partner's limits for a mint. Lowering a limit below current usage
+    /// only blocks new activity; open offers are not touched.
+    pub fn set_partner_limit(ctx: Context<SetPartnerLimit>, limits: MintLimits) -> Result<()> {
+        ctx.accounts.pool.require_role(&ctx.accounts.authority.key(), Role::PartnerManager)?;
+        let limit = &mut ctx.accounts.partner_limit;
+        let previous = limit.limits;
+        limit.limits = limits;
+        emit!(PartnerLimitSet {
+            partner: limit.partner,
+            mint: limit.mint,
+            previous: Some(previous),
+            new: limits,
+        });
+        Ok(())
+    }
+
+    pub fn remove_partner(ctx: Context<RemovePartner>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::PartnerManager)?;
+        pool.partner_count = pool.partner_count.saturating_sub(1);
+        // The record itself is closed to the authority by the `close` constraint.
+        emit!(PartnerRemoved {
+            partner: ctx.accounts.partner_record.partner,
+        });
+        Ok(())
+    }
+
+    /// Brings a pool created by an older program version up to date; call it once before
+    /// `migrate_partner`. It grows the account to `Pool::LEN` plus room for the remaining
+    /// legacy partners, with the authority topping up rent, so the newer fields and lists fit.
+    /// Only a pool whose legacy partner list nearly fills the old allocation fails to load
+    /// before this. It then writes the `initialize_pool` defaults for the settings an old pool
+    /// reads as zero, such as the offer fees and extension policy.
+    pub fn resize_pool(ctx: Context<ResizePool>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let authority = &ctx.accounts.authority;
+        // Only the prefix that every version shares can be read: discriminator, authority,
+        // legacy_max_partners and the legacy partner count.
+        let target = {
+            let data = pool.try_borrow_data()?;
+            require!(
+                data.len() >= 8 + 32 + 1 + 4 && data[..8] == Pool::DISCRIMINATOR,
+                anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+            );
+            let pool_authority = Pubkey::new_from_array(data[8..40].try_into().unwrap());
+            require_keys_eq!(pool_authority, authority.key(), OtcError::Unauthorized);
+            let legacy_count = u32::from_le_bytes(data[41..45].try_into().unwrap()) as usize;
+            Pool::LEN + 32 * legacy_count
+        };
+        if pool.data_len() < target {
+            let top_up = Rent::get()?
+                .minimum_balance(target)
+                .saturating_sub(pool.lamports());
+            if top_up > 0 {
+                invoke(
+                    &system_instruction::transfer(authority.key, pool.key, top_up),
+                    &[
+                        authority.to_account_info(),
+                        pool.clone(),
+                        ctx.accounts.system_program.to_account_info(),
+                    ],
+                )?;
+            }
+            pool.realloc(target, true)?;
         }
+
+        let mut state = Pool::try_deserialize(&mut &pool.try_borrow_data()?[..])?;
+        if state.config_version == 0 {
+            state.max_partners = state.legacy_max_partners as u32;
+            state.tier_window_secs = DEFAULT_TIER_WINDOW_SECS;
+            state.offer_fees = OfferFeeSchedule {
+                maker_fee_bps: 0,
+                taker_fee_bps: state.fee_bps,
+                taker_fee_currency: FeeCurrency::MintA,
+            };
+            state.extension_policy = ExtensionPolicy {
+                max_extensions: 1,
+                max_offer_lifetime_secs: 0,
+            };
+            state.config_version = Pool::CONFIG_VERSION;
+            state.try_serialize(&mut &mut pool.try_borrow_mut_data()?[..])?;
+        }
+        Ok(())
+    }
+
+    /// Moves one partner from the pool's legacy `Vec` into its own `PartnerRecord`. Pools
+    /// created before partner records existed call this once per legacy partner; trading
+    /// only recognises partners that have a record.
+    pub fn migrate_partner(ctx: Context<AddPartner>, partner: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_role(&ctx.accounts.authority.key(), Role::PartnerManager)?;
+        let i = pool
+            .legacy_partners
+            .iter()
+            .position(|x| *x == partner)
+            .ok_or(OtcError::PartnerNotFound)?;
+        pool.legacy_partners.swap_remove(i);
+        pool.partner_count += 1;
+        if pool.max_partners < pool.partner_count {
+            pool.max_partners = pool.partner_count;
+        }
+
+        // Legacy entries carry no metadata: they start active, untiered and without expiry.
+        let record = &mut ctx.accounts.partner_record;
+        record.pool = pool.key();
+        record.partner = partner;
+        record.bump = ctx.bumps.partner_record;
+        record.kyc_tier = 0;
+        record.onboarded_ts = Clock::get()?.unix_timestamp;
+        record.expiry_ts = i64::MAX;
+        record.status = PartnerStatus::Active;
+        record.reference_hash = [0u8; 32];
+        record.frozen = false;
+        emit!(PartnerMigrated { partner });
+        Ok(())
     }
 
     pub fn add_supported_pair(
@@ -122,7 +963,7 @@ pub mod otc_pool {
         mint_b: Pubkey,
     ) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        pool.require_role(&ctx.accounts.authority.key(), Role::ListingManager)?;
         require!(pool.whitelisted_mints.contains(&mint_a), OtcError::MintNotWhitelisted);
         require!(pool.whitelisted_mints.contains(&mint_b), OtcError::MintNotWhitelisted);
         require!(
@@ -140,7 +981,7 @@ pub mod otc_pool {
         mint_b: Pubkey,
     ) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        pool.require_role(&ctx.accounts.authority.key(), Role::ListingManager)?;
         if let Some(i) = pool.supported_pairs.iter().position(|p| p.mint_a == mint_a && p.mint_b == mint_b) {
             pool.supported_pairs.swap_remove(i);
             emit!(SupportedPairRemoved { mint_a, mint_b });
@@ -152,10 +993,10 @@ pub mod otc_pool {
 
     pub fn pause_pool(ctx: Context<ModifyPoolState>) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        pool.require_role(&ctx.accounts.authority.key(), Role::Pauser)?;
         pool.paused = true;
         emit!(PoolPaused {
-            admin: pool.authority,
+            admin: ctx.accounts.authority.key(),
             timestamp: Clock::get()?.unix_timestamp,
         });
         Ok(())
@@ -165,6 +1006,7 @@ pub mod otc_pool {
         let pool = &mut ctx.accounts.pool;
         require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
         pool.paused = false;
+        pool.emergency_paused_until = 0;
         emit!(PoolResumed {
             admin: pool.authority,
             timestamp: Clock::get()?.unix_timestamp,
@@ -172,13 +1014,130 @@ pub mod otc_pool {
         Ok(())
     }
 
-    pub fn swap_direct(
-        ctx: Context<SwapDirect>,
+    /// Sets the guardian allowed to call `emergency_pause` and how long such a pause lasts.
+    /// This also re-arms the guardian after it has used its pause. Removing the guardian
+    /// (`None`) takes a duration of 0.
+    pub fn set_guardian(
+        ctx: Context<ModifyPoolState>,
+        guardian: Option<Pubkey>,
+        emergency_pause_secs: i64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        let min_secs = if guardian.is_some() { 1 } else { 0 };
+        require!(
+            (min_secs..=Pool::MAX_EMERGENCY_PAUSE_SECS).contains(&emergency_pause_secs),
+            OtcError::InvalidPoolConfig
+        );
+        let previous = pool.guardian;
+        pool.guardian = guardian;
+        pool.emergency_pause_secs = emergency_pause_secs;
+        pool.guardian_pause_used = false;
+        emit!(GuardianUpdated { previous, new: guardian, emergency_pause_secs });
+        Ok(())
+    }
+
+    /// Halts every operation for `emergency_pause_secs`. The pause lapses on its own unless the
+    /// authority makes it permanent with `confirm_emergency_pause`; `resume_pool` ends it early.
+    /// The guardian gets one pause: it cannot pause again until the authority re-arms it with
+    /// `set_guardian`, so a compromised guardian key cannot keep the pool halted.
+    pub fn emergency_pause(ctx: Context<EmergencyPause>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let guardian = ctx.accounts.guardian.key();
+        require!(pool.guardian == Some(guardian), OtcError::Unauthorized);
+        require!(!pool.guardian_pause_used, OtcError::GuardianPauseUsed);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= pool.emergency_paused_until, OtcError::EmergencyPauseActive);
+        let expires_ts = now.checked_add(pool.emergency_pause_secs).ok_or(OtcError::MathOverflow)?;
+        pool.emergency_paused_until = expires_ts;
+        pool.guardian_pause_used = true;
+        emit!(EmergencyPaused { guardian, timestamp: now, expires_ts });
+        Ok(())
+    }
+
+    /// Turns an active emergency pause into a regular pause that only `resume_pool` lifts.
+    pub fn confirm_emergency_pause(ctx: Context<ModifyPoolState>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), OtcError::Unauthorized);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < pool.emergency_paused_until, OtcError::NoEmergencyPause);
+        pool.paused = true;
+        pool.emergency_paused_until = 0;
+        emit!(EmergencyPauseConfirmed { admin: pool.authority, timestamp: now });
+        Ok(())
+    }
+
+    /// Sets the per-operation pause bits (`PAUSE_*`). Adding bits needs the `Pauser` role;
+    /// clearing any bit is reserved to the authority.
+    pub fn set_paused_operations(ctx: Context<ModifyPoolState>, paused_operations: u8) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(paused_operations & !PAUSE_ALL == 0, OtcError::InvalidPoolConfig);
+        let lifting = pool.paused_operations & !paused_operations != 0;
+        pool.require_pause_role(&ctx.accounts.authority.key(), lifting)?;
+        let previous = pool.paused_operations;
+        pool.paused_operations = paused_operations;
+        emit!(PausedOperationsUpdated {
+            admin: ctx.accounts.authority.key(),
+            previous,
+            new: paused_operations,
+        });
+        Ok(())
+    }
+
+    /// Freezes or unfreezes trading on one supported pair: creating, accepting, swapping,
+    /// extending and amending. Cancels and expiry closes stay open so makers can withdraw.
+    pub fn set_pair_frozen(
+        ctx: Context<ModifySupportedPair>,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        frozen: bool,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.require_pause_role(&ctx.accounts.authority.key(), !frozen)?;
+        require!(
+            pool.supported_pairs.iter().any(|p| p.mint_a == mint_a && p.mint_b == mint_b),
+            OtcError::PairNotSupported
+        );
+        let position = pool.frozen_pairs.iter().position(|p| p.mint_a == mint_a && p.mint_b == mint_b);
+        match (frozen, position) {
+            (true, None) => {
+                require!(pool.frozen_pairs.len() < Pool::MAX_PAIRS, OtcError::InvalidPoolConfig);
+                pool.frozen_pairs.push(Pair { mint_a, mint_b });
+            }
+            (false, Some(i)) => {
+                pool.frozen_pairs.swap_remove(i);
+            }
+            _ => {}
+        }
+        emit!(PairFreezeUpdated {
+            admin: ctx.accounts.authority.key(),
+            mint_a,
+            mint_b,
+            frozen,
+        });
+        Ok(())
+    }
+
+    /// Freezes or unfreezes one partner for every operation, including cancels and extensions.
+    pub fn set_partner_frozen(ctx: Context<ModifyPartnerRecord>, frozen: bool) -> Result<()> {
+        ctx.accounts.pool.require_pause_role(&ctx.accounts.authority.key(), !frozen)?;
+        let record = &mut ctx.accounts.partner_record;
+        record.frozen = frozen;
+        emit!(PartnerFreezeUpdated {
+            admin: ctx.accounts.authority.key(),
+            partner: record.partner,
+            frozen,
+        });
+        Ok(())
+    }
+
+    pub fn swap_direct<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapDirect<'info>>,
         amount_a: u64,
         amount_b: u64,
     ) -> Result<()> {
         let pool = &ctx.accounts.pool;
-        require!(!pool.paused, OtcError::PoolIsPaused);
+        pool.ensure_not_paused(PAUSE_SWAP)?;
         require!(
             amount_a >= pool.min_swap_amount && amount_b >= pool.min_swap_amount,
             OtcError::SwapBelowMinimum
@@ -186,44 +1145,110 @@ pub mod otc_pool {
 
         let party_a = *ctx.accounts.party_a.key;
         let party_b = *ctx.accounts.party_b.key;
-        require!(pool.partners.contains(&party_a), OtcError::UnauthorizedPartner);
-        require!(pool.partners.contains(&party_b), OtcError::UnauthorizedPartner);
+        require_keys_neq!(party_a, party_b, OtcError::SelfTrade);
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.party_a_record.ensure_can_trade(now)?;
+        ctx.accounts.party_b_record.ensure_can_trade(now)?;
 
-        let mint_a = ctx.accounts.party_a_token_src.mint;
-        let mint_b = ctx.accounts.party_b_token_src.mint;
+        // Omitting a mint account selects native SOL for that leg.
+        let mint_a = ctx.accounts.mint_a.as_ref().map_or(NATIVE_MINT, |m| m.key());
+        let mint_b = ctx.accounts.mint_b.as_ref().map_or(NATIVE_MINT, |m| m.key());
         require!(pool.whitelisted_mints.contains(&mint_a), OtcError::MintNotWhitelisted);
         require!(pool.whitelisted_mints.contains(&mint_b), OtcError::MintNotWhitelisted);
         require!(
             pool.supported_pairs.iter().any(|p| p.mint_a == mint_a && p.mint_b == mint_b),
             OtcError::PairNotSupported
         );
+        pool.ensure_pair_active(mint_a, mint_b)?;
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.party_a_token_src.to_account_info(),
-                    to: ctx.accounts.party_b_token_dest.to_account_info(),
-                    authority: ctx.accounts.party_a.to_account_info(),
-                },
-            ),
-            amount_a,
-        )?;
+        update_partner_limit(&ctx.accounts.party_a_limit, |limit| limit.record_trade(amount_a, now))?;
+        update_partner_limit(&ctx.accounts.party_b_limit, |limit| limit.record_trade(amount_b, now))?;
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.party_b_token_src.to_account_info(),
-                    to: ctx.accounts.party_a_token_dest.to_account_info(),
-                    authority: ctx.accounts.party_b.to_account_info(),
-                },
-            ),
-            amount_b,
-        )?;
+        let token_program_a = ctx.accounts.token_program_a.to_account_info();
+        let token_program_b = ctx.accounts.token_program_b.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let leg_a = Leg {
+            mint: mint_a,
+            mint_account: ctx.accounts.mint_a.as_deref(),
+            token_program: &token_program_a,
+            hook_accounts: ctx.remaining_accounts,
+        };
+        let leg_b = Leg {
+            mint: mint_b,
+            mint_account: ctx.accounts.mint_b.as_deref(),
+            token_program: &token_program_b,
+            hook_accounts: ctx.remaining_accounts,
+        };
 
-        emit!(SwapDirectExecuted {
-            party_a,
+        // Fees come out of the amount each side sends, so the receiving party nets the rest.
+        let party_a_stats = load_partner_stats(&ctx.accounts.party_a_stats)?;
+        let party_b_stats = load_partner_stats(&ctx.accounts.party_b_stats)?;
+        let fee_bps_a = party_a_stats.as_ref().and_then(|s| pool.fee_tier(s)).map_or(pool.fee_bps, |t| t.taker_fee_bps);
+        let fee_bps_b = party_b_stats.as_ref().and_then(|s| pool.fee_tier(s)).map_or(pool.fee_bps, |t| t.taker_fee_bps);
+        let (fee_a, fee_b) = pool
+            .swap_fee_policy
+            .split_fees(amount_a, amount_b, fee_bps_a, fee_bps_b)?;
+
+        if fee_a > 0 {
+            let treasury_dest_a = leg_destination(
+                mint_a,
+                ctx.accounts.treasury.as_ref(),
+                ctx.accounts.treasury_token_account_a.as_deref(),
+            )?;
+            transfer_from_owner(
+                &leg_a,
+                &ctx.accounts.party_a,
+                ctx.accounts.party_a_token_src.as_deref(),
+                &treasury_dest_a,
+                &system_program,
+                fee_a,
+            )?;
+        }
+        let party_b_dest = leg_destination(
+            mint_a,
+            Some(&ctx.accounts.party_b),
+            ctx.accounts.party_b_token_dest.as_deref(),
+        )?;
+        transfer_from_owner(
+            &leg_a,
+            &ctx.accounts.party_a,
+            ctx.accounts.party_a_token_src.as_deref(),
+            &party_b_dest,
+            &system_program,
+            amount_a.checked_sub(fee_a).ok_or(OtcError::MathOverflow)?,
+        )?;
+
+        if fee_b > 0 {
+            let treasury_dest_b = leg_destination(
+                mint_b,
+                ctx.accounts.treasury.as_ref(),
+                ctx.accounts.treasury_token_account_b.as_deref(),
+            )?;
+            transfer_from_owner(
+                &leg_b,
+                &ctx.accounts.party_b,
+                ctx.accounts.party_b_token_src.as_deref(),
+                &treasury_dest_b,
+                &system_program,
+                fee_b,
+            )?;
+        }
+        let party_a_dest = leg_destination(
+            mint_b,
+            Some(&ctx.accounts.party_a),
+            ctx.accounts.party_a_token_dest.as_deref(),
+        )?;
+        transfer_from_owner(
+            &leg_b,
+            &ctx.accounts.party_b,
+            ctx.accounts.party_b_token_src.as_deref(),
+            &party_a_dest,
+            &system_program,
+            amount_b.checked_sub(fee_b).ok_or(OtcError::MathOverflow)?,
+        )?;
+
+        emit!(SwapDirectExecuted {
+            party_a,
             party_b,
             mint_a,
             mint_b,
@@ -231,18 +1256,28 @@ pub mod otc_pool {
             filled_amount_b: amount_b,
             remaining_amount_a: amount_a,
             remaining_amount_b: amount_b,
+            fee_a,
+            fee_b,
         });
+
+        let whitelisted_mints = &ctx.accounts.pool.whitelisted_mints;
+        for stats in [&ctx.accounts.party_a_stats, &ctx.accounts.party_b_stats] {
+            record_partner_volume(stats, &[(mint_a, amount_a), (mint_b, amount_b)], whitelisted_mints)?;
+        }
         Ok(())
     }
 
-    pub fn create_offer(
-        ctx: Context<CreateOffer>,
+    pub fn create_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateOffer<'info>>,
+        offer_id: u64,
         amount_a: u64,
         amount_b: u64,
         expiration_ts: i64,
+        allowed_takers: Vec<Pubkey>,
+        fill_constraints: FillConstraints,
     ) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        require!(!pool.paused, OtcError::PoolIsPaused);
+        pool.ensure_not_paused(PAUSE_CREATE)?;
         require!(
             amount_a >= pool.min_swap_amount && amount_b >= pool.min_swap_amount,
             OtcError::SwapBelowMinimum
@@ -253,55 +1288,125 @@ pub mod otc_pool {
             expiration_ts <= clock.unix_timestamp + pool.max_expiration_secs,
             OtcError::ExpirationTooLong
         );
+        require!(
+            expiration_ts >= clock.unix_timestamp + MIN_OFFER_LIFETIME_SECS,
+            OtcError::ExpirationTooSoon
+        );
 
         let maker = *ctx.accounts.maker.key;
-        require!(pool.partners.contains(&maker), OtcError::UnauthorizedPartner);
+        let crank_deposit = pool.crank_bounty_lamports;
+        ctx.accounts.maker_record.ensure_can_trade(clock.unix_timestamp)?;
+        require!(allowed_takers.len() <= Offer::MAX_ALLOWED_TAKERS, OtcError::TooManyAllowedTakers);
+        require!(fill_constraints.min_fill_b <= amount_b, OtcError::InvalidFillConstraints);
 
-        let mint_a = ctx.accounts.mint_a.key();
-        let mint_b = ctx.accounts.mint_b.key();
+        // Omitting a mint account selects native SOL for that leg.
+        let mint_a = ctx.accounts.mint_a.as_ref().map_or(NATIVE_MINT, |m| m.key());
+        let mint_b = ctx.accounts.mint_b.as_ref().map_or(NATIVE_MINT, |m| m.key());
         require!(pool.whitelisted_mints.contains(&mint_a), OtcError::MintNotWhitelisted);
         require!(pool.whitelisted_mints.contains(&mint_b), OtcError::MintNotWhitelisted);
         require!(
             pool.supported_pairs.iter().any(|p| p.mint_a == mint_a && p.mint_b == mint_b),
             OtcError::PairNotSupported
         );
+        pool.ensure_pair_active(mint_a, mint_b)?;
+
+        let decimals_a = ctx.accounts.mint_a.as_ref().map_or(NATIVE_DECIMALS, |m| m.decimals);
+        let decimals_b = ctx.accounts.mint_b.as_ref().map_or(NATIVE_DECIMALS, |m| m.decimals);
+
+        // Native offers keep their escrowed lamports on the offer account itself.
+        let escrow_dest = if mint_a == NATIVE_MINT {
+            ctx.accounts.offer.to_account_info()
+        } else {
+            ctx.accounts
+                .escrow_account
+                .as_ref()
+                .ok_or(OtcError::MissingLegAccount)?
+                .to_account_info()
+        };
+        let token_program_a = ctx.accounts.token_program_a.to_account_info();
+        let leg_a = Leg {
+            mint: mint_a,
+            mint_account: ctx.accounts.mint_a.as_deref(),
+            token_program: &token_program_a,
+            hook_accounts: ctx.remaining_accounts,
+        };
+        transfer_from_owner(
+            &leg_a,
+            &ctx.accounts.maker.to_account_info(),
+            ctx.accounts.maker_token_src.as_deref(),
+            &escrow_dest,
+            &ctx.accounts.system_program.to_account_info(),
+            amount_a,
+        )?;
+
+        // The maker funds the keeper bounty for closing this offer once it expires. The deposit
+        // sits on the offer account and comes back on cancel or a full fill.
+        if crank_deposit > 0 {
+            invoke(
+                &system_instruction::transfer(&maker, &ctx.accounts.offer.key(), crank_deposit),
+                &[
+                    ctx.accounts.maker.to_account_info(),
+                    ctx.accounts.offer.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        // Transfer-fee mints withhold part of the deposit, so record what the escrow
+        // actually holds to keep `amount_a` and the fill ratio exact.
+        let escrowed_amount_a = match ctx.accounts.escrow_account.as_mut() {
+            Some(escrow_account) => {
+                escrow_account.reload()?;
+                escrow_account.amount
+            }
+            None => amount_a,
+        };
+        update_partner_limit(&ctx.accounts.maker_limit, |limit| {
+            limit.check_single_trade(escrowed_amount_a)?;
+            limit.add_open_escrow(escrowed_amount_a)
+        })?;
 
         let offer = &mut ctx.accounts.offer;
+        offer.pool = pool.key();
         offer.maker = maker;
+        offer.offer_id = offer_id;
         offer.mint_a = mint_a;
         offer.mint_b = mint_b;
-        offer.original_amount_a = amount_a;
+        offer.original_amount_a = escrowed_amount_a;
         offer.original_amount_b = amount_b;
-        offer.amount_a = amount_a;
+        offer.amount_a = escrowed_amount_a;
         offer.amount_b = amount_b;
         offer.expiration_ts = expiration_ts;
         offer.fulfilled = false;
         offer.bump = ctx.bumps.offer;
-
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.maker_token_src.to_account_info(),
-                    to: ctx.accounts.escrow_account.to_account_info(),
-                    authority: ctx.accounts.maker.to_account_info(),
-                },
-            ),
-            amount_a,
-        )?;
+        offer.escrow_bump = ctx.bumps.escrow_account.unwrap_or_default();
+        offer.decimals_a = decimals_a;
+        offer.decimals_b = decimals_b;
+        offer.created_ts = clock.unix_timestamp;
+        offer.allowed_takers = allowed_takers.clone();
+        offer.fill_constraints = fill_constraints;
+        offer.fill_count = 0;
+        offer.sequence = 0;
+        offer.extension_count = 0;
+        offer.crank_deposit = crank_deposit;
 
         emit!(OfferCreated {
             maker,
+            offer_id,
             mint_a,
             mint_b,
-            amount_a,
+            amount_a: escrowed_amount_a,
             amount_b,
             expiration_ts,
+            decimals_a,
+            decimals_b,
+            allowed_takers,
+            fill_constraints,
         });
         Ok(())
     }
 
-  pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+  pub fn cancel_offer<'info>(ctx: Context<'_, '_, '_, 'info, CancelOffer<'info>>) -> Result<()> {
     let pool = &ctx.accounts.pool;
     let maker = *ctx.accounts.maker.key;
     let offer_account = &ctx.accounts.offer;
@@ -309,119 +1414,241 @@ pub mod otc_pool {
     require_keys_eq!(offer_account.maker, maker, OtcError::Unauthorized);
     require!(!offer_account.fulfilled, OtcError::OfferAlreadyFulfilled);
     require!(ctx.accounts.clock.unix_timestamp <= offer_account.expiration_ts, OtcError::OfferExpired);
+    pool.ensure_not_paused(PAUSE_CANCEL)?;
+    ensure_partner_not_frozen(&ctx.accounts.maker_record)?;
 
-    let fee_amount = (offer_account.amount_a as u128)
-        .checked_mul(pool.fee_bps as u128).unwrap()
-        .checked_div(10_000).unwrap() as u64;
+    let resting_secs = ctx.accounts.clock.unix_timestamp.saturating_sub(offer_account.created_ts);
+    let fee_amount = pool.cancellation_fee_policy.fee(offer_account.amount_a, resting_secs)?;
     let net = offer_account.amount_a.checked_sub(fee_amount).unwrap();
 
-    let seeds = &[b"offer", offer_account.maker.as_ref(), &[offer_account.bump]];
+    let token_program = ctx.accounts.token_program.to_account_info();
+    let leg_a = Leg {
+        mint: offer_account.mint_a,
+        mint_account: ctx.accounts.mint_a.as_ref(),
+        token_program: &token_program,
+        hook_accounts: ctx.remaining_accounts,
+    };
+    if fee_amount > 0 {
+        let treasury_dest = leg_destination(
+            offer_account.mint_a,
+            ctx.accounts.treasury.as_ref(),
+            ctx.accounts.treasury_token_account.as_ref(),
+        )?;
+        transfer_from_escrow(
+            &leg_a,
+            offer_account,
+            ctx.accounts.escrow_account.as_ref(),
+            &treasury_dest,
+            fee_amount,
+        )?;
+    }
 
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.escrow_account.to_account_info(),
-                to: ctx.accounts.treasury_token_account.to_account_info(),
-                authority: ctx.accounts.offer.to_account_info(),
-            },
-        )
-        .with_signer(&[seeds]),
-        fee_amount,
+    let maker_dest = leg_destination(
+        offer_account.mint_a,
+        Some(&ctx.accounts.maker.to_account_info()),
+        ctx.accounts.maker_token_dest.as_ref(),
     )?;
-
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.escrow_account.to_account_info(),
-                to: ctx.accounts.maker_token_dest.to_account_info(),
-                authority: ctx.accounts.offer.to_account_info(),
-            },
-        )
-        .with_signer(&[seeds]),
+    transfer_from_escrow(
+        &leg_a,
+        offer_account,
+        ctx.accounts.escrow_account.as_ref(),
+        &maker_dest,
         net,
     )?;
 
+    update_partner_limit(&ctx.accounts.maker_limit, |limit| {
+        limit.release_open_escrow(offer_account.amount_a);
+        Ok(())
+    })?;
+
     // Now get mutable reference after all immutable uses
+    let maker_info = ctx.accounts.maker.to_account_info();
     let offer = &mut ctx.accounts.offer;
     offer.fulfilled = true;
-    emit!(OfferCancelled { maker });
+    release_crank_deposit(offer, &maker_info)?;
+    emit!(OfferCancelled {
+        maker,
+        offer_id: offer.offer_id,
+        refunded_amount_a: net,
+        cancellation_fee: fee_amount,
+    });
     Ok(())
 }
 
- pub fn accept_offer(ctx: Context<AcceptOffer>, fill_amount_b: u64) -> Result<()> {
+ pub fn accept_offer<'info>(
+    ctx: Context<'_, '_, '_, 'info, AcceptOffer<'info>>,
+    fill_amount_b: u64,
+    min_receive_a: u64,
+    expected_sequence: u64,
+) -> Result<()> {
     let pool = &ctx.accounts.pool;
     let offer_account = &ctx.accounts.offer;
     let taker = *ctx.accounts.taker.key;
 
-    require!(!pool.paused, OtcError::PoolIsPaused);
+    pool.ensure_not_paused(PAUSE_ACCEPT)?;
+    pool.ensure_pair_active(offer_account.mint_a, offer_account.mint_b)?;
     require!(!offer_account.fulfilled, OtcError::OfferAlreadyFulfilled);
     require!(ctx.accounts.clock.unix_timestamp <= offer_account.expiration_ts, OtcError::OfferExpired);
-    require!(pool.partners.contains(&taker), OtcError::UnauthorizedPartner);
+    require!(offer_account.sequence == expected_sequence, OtcError::OfferSequenceMismatch);
+    require_keys_neq!(taker, offer_account.maker, OtcError::SelfTrade);
+    require!(
+        offer_account.allowed_takers.is_empty() || offer_account.allowed_takers.contains(&taker),
+        OtcError::TakerNotAllowed
+    );
+    ctx.accounts.maker_record.ensure_can_trade(ctx.accounts.clock.unix_timestamp)?;
+    ctx.accounts.taker_record.ensure_can_trade(ctx.accounts.clock.unix_timestamp)?;
 
     require!(fill_amount_b > 0 && fill_amount_b <= offer_account.amount_b, OtcError::InvalidFillAmount);
+    offer_account.check_fill(fill_amount_b)?;
     let take_b = fill_amount_b;
-    let take_a = (offer_account.original_amount_a as u128)
-        .checked_mul(take_b as u128).unwrap()
-        .checked_div(offer_account.original_amount_b as u128).unwrap() as u64;
+    // The completing fill takes all remaining mint_a so rounding dust is not left in escrow.
+    let take_a = if take_b == offer_account.amount_b {
+        offer_account.amount_a
+    } else {
+        (offer_account.original_amount_a as u128)
+            .checked_mul(take_b as u128).unwrap()
+            .checked_div(offer_account.original_amount_b as u128).unwrap() as u64
+    };
     require!(take_a <= offer_account.amount_a, OtcError::InvalidFillAmount);
 
-    let fee_amount = (take_a as u128)
-        .checked_mul(pool.fee_bps as u128).unwrap()
-        .checked_div(10_000).unwrap() as u64;
-    let net_a = take_a.checked_sub(fee_amount).unwrap();
+    // The taker fee comes out of the mint_a they receive or is paid on top in mint_b. The maker
+    // fee is always settled in mint_b, since the escrowed mint_a is fully committed to fills.
+    // Partners with a fee tier pay their tier's rate in place of the pool default.
+    let fees = pool.offer_fees;
+    let maker_stats = load_partner_stats(&ctx.accounts.maker_stats)?;
+    let taker_stats = load_partner_stats(&ctx.accounts.taker_stats)?;
+    let maker_fee_bps = maker_stats.as_ref().and_then(|s| pool.fee_tier(s)).map_or(fees.maker_fee_bps, |t| t.maker_fee_bps);
+    let taker_fee_bps = taker_stats.as_ref().and_then(|s| pool.fee_tier(s)).map_or(fees.taker_fee_bps, |t| t.taker_fee_bps);
+    let taker_fee = match fees.taker_fee_currency {
+        FeeCurrency::MintA => bps_fee(take_a, taker_fee_bps)?,
+        FeeCurrency::MintB => bps_fee(take_b, taker_fee_bps)?,
+    };
+    let (taker_fee_a, taker_fee_b) = match fees.taker_fee_currency {
+        FeeCurrency::MintA => (taker_fee, 0),
+        FeeCurrency::MintB => (0, taker_fee),
+    };
+    let maker_fee = bps_fee(take_b, maker_fee_bps.max(0) as u16)?;
+    let maker_rebate_due = bps_fee(take_b, maker_fee_bps.min(0).unsigned_abs())?;
+    let net_a = take_a.checked_sub(taker_fee_a).ok_or(OtcError::MathOverflow)?;
+    let net_b = take_b.checked_sub(maker_fee).ok_or(OtcError::MathOverflow)?;
 
-    let seeds = &[b"offer", offer_account.maker.as_ref(), &[offer_account.bump]];
+    let token_program_a = ctx.accounts.token_program_a.to_account_info();
+    let token_program_b = ctx.accounts.token_program_b.to_account_info();
+    let leg_a = Leg {
+        mint: offer_account.mint_a,
+        mint_account: ctx.accounts.mint_a.as_deref(),
+        token_program: &token_program_a,
+        hook_accounts: ctx.remaining_accounts,
+    };
+    let leg_b = Leg {
+        mint: offer_account.mint_b,
+        mint_account: ctx.accounts.mint_b.as_deref(),
+        token_program: &token_program_b,
+        hook_accounts: ctx.remaining_accounts,
+    };
 
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.maker.to_account_info(),
-                to: ctx.accounts.maker_token_dest.to_account_info(),
-                authority: ctx.accounts.taker.to_account_info(),
-            },
-        ),
-        take_b,
-    )?;
+    // What actually reaches the taker after the taker fee and any mint transfer fee.
+    let received_a = net_a.checked_sub(leg_a.transfer_fee(net_a)?).ok_or(OtcError::MathOverflow)?;
+    require!(received_a >= min_receive_a, OtcError::SlippageExceeded);
 
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.escrow_account.to_account_info(),
-                to: ctx.accounts.treasury_token_account.to_account_info(),
-                authority: ctx.accounts.offer.to_account_info(),
-            },
-        )
-        .with_signer(&[seeds]),
-        fee_amount,
+    let maker_dest = leg_destination(
+        offer_account.mint_b,
+        Some(&ctx.accounts.maker),
+        ctx.accounts.maker_token_dest.as_deref(),
+    )?;
+    transfer_from_owner(
+        &leg_b,
+        &ctx.accounts.taker,
+        ctx.accounts.taker_token_src.as_deref(),
+        &maker_dest,
+        &ctx.accounts.system_program.to_account_info(),
+        net_b,
     )?;
 
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.escrow_account.to_account_info(),
-                to: ctx.accounts.taker_token_dest.to_account_info(),
-                authority: ctx.accounts.offer.to_account_info(),
-            },
-        )
-        .with_signer(&[seeds]),
+    let fee_b = maker_fee.checked_add(taker_fee_b).ok_or(OtcError::MathOverflow)?;
+    if fee_b > 0 {
+        let treasury_dest_b = leg_destination(
+            offer_account.mint_b,
+            ctx.accounts.treasury.as_ref(),
+            ctx.accounts.treasury_token_account_b.as_deref(),
+        )?;
+        transfer_from_owner(
+            &leg_b,
+            &ctx.accounts.taker,
+            ctx.accounts.taker_token_src.as_deref(),
+            &treasury_dest_b,
+            &ctx.accounts.system_program.to_account_info(),
+            fee_b,
+        )?;
+    }
+
+    if taker_fee_a > 0 {
+        let treasury_dest = leg_destination(
+            offer_account.mint_a,
+            ctx.accounts.treasury.as_ref(),
+            ctx.accounts.treasury_token_account.as_deref(),
+        )?;
+        transfer_from_escrow(
+            &leg_a,
+            offer_account,
+            ctx.accounts.escrow_account.as_deref(),
+            &treasury_dest,
+            taker_fee_a,
+        )?;
+    }
+
+    let taker_dest = leg_destination(
+        offer_account.mint_a,
+        Some(&ctx.accounts.taker),
+        ctx.accounts.taker_token_dest.as_deref(),
+    )?;
+    transfer_from_escrow(
+        &leg_a,
+        offer_account,
+        ctx.accounts.escrow_account.as_deref(),
+        &taker_dest,
         net_a,
     )?;
 
+    let maker_rebate = if maker_rebate_due > 0 {
+        let rebate_authority = ctx
+            .accounts
+            .rebate_authority
+            .as_ref()
+            .zip(ctx.bumps.rebate_authority);
+        pay_rebate(
+            &leg_b,
+            &pool.key(),
+            ctx.accounts.rebate_vault.as_ref().ok_or(OtcError::MissingLegAccount)?,
+            rebate_authority,
+            &maker_dest,
+            maker_rebate_due,
+        )?
+    } else {
+        0
+    };
+
     // Now get mutable reference after all immutable uses
     let offer = &mut ctx.accounts.offer;
     offer.amount_b = offer.amount_b.checked_sub(take_b).unwrap();
     offer.amount_a = offer.amount_a.checked_sub(take_a).unwrap();
+    offer.fill_count = offer.fill_count.saturating_add(1);
     if offer.amount_b == 0 {
         offer.fulfilled = true;
+        release_crank_deposit(offer, &ctx.accounts.maker)?;
     }
 
+    let now = ctx.accounts.clock.unix_timestamp;
+    update_partner_limit(&ctx.accounts.maker_limit, |limit| {
+        limit.record_volume(take_a, now)?;
+        limit.release_open_escrow(take_a);
+        Ok(())
+    })?;
+    update_partner_limit(&ctx.accounts.taker_limit, |limit| limit.record_trade(take_b, now))?;
+
     emit!(OfferExecuted {
         maker: offer.maker,
+        offer_id: offer.offer_id,
         taker,
         mint_a: offer.mint_a,
         mint_b: offer.mint_b,
@@ -429,10 +1656,137 @@ pub mod otc_pool {
         filled_amount_b: take_b,
         remaining_amount_a: offer.amount_a,
         remaining_amount_b: offer.amount_b,
+        fill_count: offer.fill_count,
+        maker_fee,
+        maker_rebate,
+        taker_fee,
+        taker_fee_mint: match fees.taker_fee_currency {
+            FeeCurrency::MintA => offer.mint_a,
+            FeeCurrency::MintB => offer.mint_b,
+        },
     });
+
+    let (mint_a, mint_b) = (offer.mint_a, offer.mint_b);
+    let whitelisted_mints = &ctx.accounts.pool.whitelisted_mints;
+    for stats in [&ctx.accounts.maker_stats, &ctx.accounts.taker_stats] {
+        record_partner_volume(stats, &[(mint_a, take_a), (mint_b, take_b)], whitelisted_mints)?;
+    }
     Ok(())
 }
 
+    /// Replaces an open offer's remaining terms with `new_amount_a` for `new_amount_b`, which
+    /// also sets the price of later fills. Extra mint_a is pulled from the maker and any surplus
+    /// is refunded without a cancellation fee. Bumps `sequence` so takers who priced the old
+    /// terms are rejected.
+    pub fn amend_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, AmendOffer<'info>>,
+        new_amount_a: u64,
+        new_amount_b: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let offer = &ctx.accounts.offer;
+        let clock = Clock::get()?;
+
+        require!(!offer.fulfilled, OtcError::OfferAlreadyFulfilled);
+        require!(clock.unix_timestamp <= offer.expiration_ts, OtcError::OfferExpired);
+        pool.ensure_not_paused(PAUSE_CREATE)?;
+        pool.ensure_pair_active(offer.mint_a, offer.mint_b)?;
+        ctx.accounts.maker_record.ensure_can_trade(clock.unix_timestamp)?;
+        require!(new_amount_a > 0 && new_amount_b > 0, OtcError::SwapBelowMinimum);
+        require!(
+            new_amount_a >= pool.min_swap_amount && new_amount_b >= pool.min_swap_amount,
+            OtcError::SwapBelowMinimum
+        );
+        require!(
+            offer.fill_constraints.min_fill_b <= new_amount_b,
+            OtcError::InvalidFillConstraints
+        );
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let leg_a = Leg {
+            mint: offer.mint_a,
+            mint_account: ctx.accounts.mint_a.as_deref(),
+            token_program: &token_program,
+            hook_accounts: ctx.remaining_accounts,
+        };
+        let old_amount_a = offer.amount_a;
+        let old_amount_b = offer.amount_b;
+
+        let escrowed_amount_a = if new_amount_a > old_amount_a {
+            let escrow_dest = if leg_a.is_native() {
+                offer.to_account_info()
+            } else {
+                ctx.accounts
+                    .escrow_account
+                    .as_ref()
+                    .ok_or(OtcError::MissingLegAccount)?
+                    .to_account_info()
+            };
+            transfer_from_owner(
+                &leg_a,
+                &ctx.accounts.maker.to_account_info(),
+                ctx.accounts.maker_token_account.as_deref(),
+                &escrow_dest,
+                &ctx.accounts.system_program.to_account_info(),
+                new_amount_a - old_amount_a,
+            )?;
+            // As in `create_offer`, count what the escrow actually received.
+            let received = match ctx.accounts.escrow_account.as_mut() {
+                Some(escrow_account) => {
+                    let before = escrow_account.amount;
+                    escrow_account.reload()?;
+                    escrow_account.amount.checked_sub(before).ok_or(OtcError::MathOverflow)?
+                }
+                None => new_amount_a - old_amount_a,
+            };
+            let escrowed = old_amount_a.checked_add(received).ok_or(OtcError::MathOverflow)?;
+            update_partner_limit(&ctx.accounts.maker_limit, |limit| {
+                limit.check_single_trade(escrowed)?;
+                limit.add_open_escrow(received)
+            })?;
+            escrowed
+        } else {
+            let refund = old_amount_a - new_amount_a;
+            if refund > 0 {
+                let maker_dest = leg_destination(
+                    offer.mint_a,
+                    Some(&ctx.accounts.maker.to_account_info()),
+                    ctx.accounts.maker_token_account.as_deref(),
+                )?;
+                transfer_from_escrow(
+                    &leg_a,
+                    offer,
+                    ctx.accounts.escrow_account.as_deref(),
+                    &maker_dest,
+                    refund,
+                )?;
+                update_partner_limit(&ctx.accounts.maker_limit, |limit| {
+                    limit.release_open_escrow(refund);
+                    Ok(())
+                })?;
+            }
+            new_amount_a
+        };
+
+        let offer = &mut ctx.accounts.offer;
+        offer.original_amount_a = escrowed_amount_a;
+        offer.original_amount_b = new_amount_b;
+        offer.amount_a = escrowed_amount_a;
+        offer.amount_b = new_amount_b;
+        offer.sequence = offer.sequence.checked_add(1).ok_or(OtcError::MathOverflow)?;
+
+        emit!(OfferAmended {
+            maker: offer.maker,
+            offer_id: offer.offer_id,
+            old_amount_a,
+            old_amount_b,
+            new_amount_a: escrowed_amount_a,
+            new_amount_b,
+            sequence: offer.sequence,
+        });
+        Ok(())
+    }
+
     pub fn extend_offer(ctx: Context<ExtendOffer>, new_expiration_ts: i64) -> Result<()> {
         let offer = &mut ctx.accounts.offer;
         let maker = *ctx.accounts.maker.key;
@@ -440,109 +1794,1342 @@ pub mod otc_pool {
 
         require_keys_eq!(offer.maker, maker, OtcError::Unauthorized);
         require!(!offer.fulfilled, OtcError::OfferAlreadyFulfilled);
+        require!(clock.unix_timestamp <= offer.expiration_ts, OtcError::OfferExpired);
+        ctx.accounts.pool.ensure_not_paused(PAUSE_EXTEND)?;
+        ctx.accounts.pool.ensure_pair_active(offer.mint_a, offer.mint_b)?;
+        ensure_partner_not_frozen(&ctx.accounts.maker_record)?;
         require!(new_expiration_ts > offer.expiration_ts, OtcError::InvalidExtension);
         require!(
             new_expiration_ts <= clock.unix_timestamp + ctx.accounts.pool.max_expiration_secs,
             OtcError::ExpirationTooLong
         );
+        let policy = ctx.accounts.pool.extension_policy;
+        require!(offer.extension_count < policy.max_extensions, OtcError::ExtensionLimitReached);
+        require!(
+            policy.max_offer_lifetime_secs == 0
+                || new_expiration_ts.saturating_sub(offer.created_ts) <= policy.max_offer_lifetime_secs,
+            OtcError::OfferLifetimeExceeded
+        );
 
         offer.expiration_ts = new_expiration_ts;
-        emit!(OfferExtended { maker, new_expiration_ts });
+        offer.extension_count += 1;
+        emit!(OfferExtended {
+            maker,
+            offer_id: offer.offer_id,
+            new_expiration_ts,
+            extension_count: offer.extension_count,
+        });
         Ok(())
     }
 }
 
-/// ========== State & Events ==========
+// ========== Helpers ==========
+
+/// Accounts needed to move one leg of a trade. `mint_account` is `None` for native SOL.
+struct Leg<'a, 'info> {
+    mint: Pubkey,
+    mint_account: Option<&'a InterfaceAccount<'info, Mint>>,
+    token_program: &'a AccountInfo<'info>,
+    /// Extra accounts resolved by Token-2022 for mints with a transfer hook.
+    hook_accounts: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> Leg<'a, 'info> {
+    fn is_native(&self) -> bool {
+        self.mint == NATIVE_MINT
+    }
+
+    fn mint_account(&self) -> Result<&'a InterfaceAccount<'info, Mint>> {
+        Ok(self.mint_account.ok_or(OtcError::MissingLegAccount)?)
+    }
+
+    /// `transfer_checked` through the leg's token program, appending any accounts the mint's
+    /// transfer hook requires.
+    fn transfer_checked(
+        &self,
+        from: &AccountInfo<'info>,
+        to: &AccountInfo<'info>,
+        authority: &AccountInfo<'info>,
+        amount: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let mint = self.mint_account()?;
+        spl_token_2022::onchain::invoke_transfer_checked(
+            self.token_program.key,
+            from.clone(),
+            mint.to_account_info(),
+            to.clone(),
+            authority.clone(),
+            self.hook_accounts,
+            amount,
+            mint.decimals,
+            signer_seeds,
+        )?;
+        Ok(())
+    }
+
+    /// Transfer fee the mint withholds when `amount` is sent; 0 for mints without one.
+    fn transfer_fee(&self, amount: u64) -> Result<u64> {
+        if self.is_native() || *self.token_program.key != spl_token_2022::ID {
+            return Ok(0);
+        }
+        let mint_info = self.mint_account()?.to_account_info();
+        let mint_data = mint_info.try_borrow_data()?;
+        let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+        match mint.get_extension::<TransferFeeConfig>() {
+            Ok(config) => Ok(config
+                .calculate_epoch_fee(Clock::get()?.epoch, amount)
+                .ok_or(OtcError::MathOverflow)?),
+            Err(_) => Ok(0),
+        }
+    }
+
+    /// Token-2022 refuses to close accounts holding withheld transfer fees, so sweep them to
+    /// the mint first. Harvesting is permissionless.
+    fn harvest_withheld_fees(&self, account: &AccountInfo<'info>) -> Result<()> {
+        if *self.token_program.key != spl_token_2022::ID {
+            return Ok(());
+        }
+        let mint_info = self.mint_account()?.to_account_info();
+        let has_transfer_fee = {
+            let mint_data = mint_info.try_borrow_data()?;
+            let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+            mint.get_extension::<TransferFeeConfig>().is_ok()
+        };
+        if has_transfer_fee {
+            invoke(
+                &harvest_withheld_tokens_to_mint(self.token_program.key, mint_info.key, &[account.key])?,
+                &[mint_info, account.clone()],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the full escrow balance, less the pool's cancellation fee, to the maker and closes
+/// the escrow token account, sending its rent to the maker. Native offers escrow lamports on
+/// the offer account itself, which go back to the maker when the caller closes the offer.
+/// Returns the amount refunded and the fee sent to the treasury.
+#[allow(clippy::too_many_arguments)]
+fn refund_and_close_escrow<'info>(
+    leg_a: &Leg<'_, 'info>,
+    offer: &Account<'info, Offer>,
+    escrow_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    maker: &AccountInfo<'info>,
+    maker_token_dest: Option<&AccountInfo<'info>>,
+    treasury_dest: Option<&AccountInfo<'info>>,
+    fee_policy: &CancellationFeePolicy,
+    now: i64,
+) -> Result<(u64, u64)> {
+    let resting_secs = now.saturating_sub(offer.created_ts);
+    if leg_a.is_native() {
+        let fee = fee_policy.fee(offer.amount_a, resting_secs)?;
+        if fee > 0 {
+            let treasury_dest = treasury_dest.ok_or(OtcError::MissingLegAccount)?;
+            transfer_from_escrow(leg_a, offer, None, treasury_dest, fee)?;
+        }
+        return Ok((offer.amount_a - fee, fee));
+    }
+    let escrow_account = escrow_account.ok_or(OtcError::MissingLegAccount)?;
+    let maker_token_dest = maker_token_dest.ok_or(OtcError::MissingLegAccount)?;
+
+    // Refund the actual balance rather than `amount_a` so stray deposits cannot block the close.
+    let balance = escrow_account.amount;
+    let fee = fee_policy.fee(balance, resting_secs)?;
+    if fee > 0 {
+        let treasury_dest = treasury_dest.ok_or(OtcError::MissingLegAccount)?;
+        transfer_from_escrow(leg_a, offer, Some(escrow_account), treasury_dest, fee)?;
+    }
+    let refunded_amount_a = balance - fee;
+    let offer_id_bytes = offer.offer_id.to_le_bytes();
+    let seeds = &[b"offer", offer.maker.as_ref(), offer_id_bytes.as_ref(), &[offer.bump]];
+
+    leg_a.transfer_checked(
+        &escrow_account.to_account_info(),
+        maker_token_dest,
+        &offer.to_account_info(),
+        refunded_amount_a,
+        &[seeds],
+    )?;
+    leg_a.harvest_withheld_fees(&escrow_account.to_account_info())?;
+
+    token_interface::close_account(
+        CpiContext::new(
+            leg_a.token_program.clone(),
+            CloseAccount {
+                account: escrow_account.to_account_info(),
+                destination: maker.clone(),
+                authority: offer.to_account_info(),
+            },
+        )
+        .with_signer(&[seeds]),
+    )?;
+
+    Ok((refunded_amount_a, fee))
+}
+
+/// Moves an offer's crank deposit to `dest` and returns the amount moved.
+fn release_crank_deposit<'info>(
+    offer: &mut Account<'info, Offer>,
+    dest: &AccountInfo<'info>,
+) -> Result<u64> {
+    let deposit = offer.crank_deposit;
+    if deposit > 0 {
+        **offer.to_account_info().try_borrow_mut_lamports()? -= deposit;
+        **dest.try_borrow_mut_lamports()? += deposit;
+        offer.crank_deposit = 0;
+    }
+    Ok(deposit)
+}
+
+/// `amount * bps / 10_000`, rounded down.
+fn bps_fee(amount: u64, bps: u16) -> Result<u64> {
+    let fee = (amount as u128)
+        .checked_mul(bps as u128)
+        .ok_or(OtcError::MathOverflow)?
+        / 10_000;
+    Ok(fee as u64)
+}
+
+/// Pays a maker rebate of up to `amount` out of the pool's rebate vault for the leg's mint,
+/// capped at what the vault holds so an unfunded vault does not block fills. Returns the
+/// amount paid.
+fn pay_rebate<'info>(
+    leg: &Leg<'_, 'info>,
+    pool: &Pubkey,
+    rebate_vault: &AccountInfo<'info>,
+    rebate_authority: Option<(&AccountInfo<'info>, u8)>,
+    destination: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<u64> {
+    // A vault that was never initialized has nothing to pay out.
+    if rebate_vault.data_is_empty() {
+        return Ok(0);
+    }
+
+    if leg.is_native() {
+        let rent_exempt = Rent::get()?.minimum_balance(rebate_vault.data_len());
+        let paid = amount.min(rebate_vault.lamports().saturating_sub(rent_exempt));
+        **rebate_vault.try_borrow_mut_lamports()? -= paid;
+        **destination.try_borrow_mut_lamports()? += paid;
+        return Ok(paid);
+    }
+
+    let (authority, bump) = rebate_authority.ok_or(OtcError::MissingLegAccount)?;
+    let balance = TokenAccount::try_deserialize(&mut &rebate_vault.try_borrow_data()?[..])?.amount;
+    let paid = amount.min(balance);
+    if paid > 0 {
+        let seeds = &[b"rebate_authority", pool.as_ref(), &[bump]];
+        leg.transfer_checked(rebate_vault, destination, authority, paid, &[seeds])?;
+    }
+    Ok(paid)
+}
+
+/// Applies `f` to the `PartnerLimit` stored at `limit_info`, whose address the caller has
+/// already derived. An uninitialized address means the partner has no limit for that mint.
+fn update_partner_limit(
+    limit_info: &AccountInfo<'_>,
+    f: impl FnOnce(&mut PartnerLimit) -> Result<()>,
+) -> Result<()> {
+    if limit_info.data_is_empty() {
+        return Ok(());
+    }
+    let mut limit = PartnerLimit::try_deserialize(&mut &limit_info.try_borrow_data()?[..])?;
+    f(&mut limit)?;
+    limit.try_serialize(&mut &mut limit_info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+/// Reads the `PartnerStats` at `stats_info`, whose address the caller has already derived.
+/// `None` until `init_partner_stats` has been called for the partner.
+fn load_partner_stats(stats_info: &AccountInfo<'_>) -> Result<Option<PartnerStats>> {
+    if stats_info.data_is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(PartnerStats::try_deserialize(&mut &stats_info.try_borrow_data()?[..])?))
+}
+
+/// Adds a trade's per-mint volumes to the partner's stats. Partners without stats are skipped.
+fn record_partner_volume(
+    stats_info: &AccountInfo<'_>,
+    volumes: &[(Pubkey, u64)],
+    whitelisted_mints: &[Pubkey],
+) -> Result<()> {
+    let Some(mut stats) = load_partner_stats(stats_info)? else {
+        return Ok(());
+    };
+    for &(mint, amount) in volumes {
+        stats.record_volume(mint, amount, whitelisted_mints)?;
+    }
+    stats.try_serialize(&mut &mut stats_info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+/// Rejects when the `PartnerRecord` at `record_info` is frozen. A removed partner's record is
+/// closed, which does not block them from winding down their offers.
+fn ensure_partner_not_frozen(record_info: &AccountInfo<'_>) -> Result<()> {
+    if record_info.data_is_empty() {
+        return Ok(());
+    }
+    let record = PartnerRecord::try_deserialize(&mut &record_info.try_borrow_data()?[..])?;
+    require!(!record.frozen, OtcError::PartnerFrozen);
+    Ok(())
+}
+
+/// Resolves where a leg is paid: the wallet itself for native legs, its token account otherwise.
+fn leg_destination<'info>(
+    mint: Pubkey,
+    wallet: Option<&AccountInfo<'info>>,
+    token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+) -> Result<AccountInfo<'info>> {
+    if mint == NATIVE_MINT {
+        Ok(wallet.ok_or(OtcError::MissingLegAccount)?.clone())
+    } else {
+        Ok(token_account.ok_or(OtcError::MissingLegAccount)?.to_account_info())
+    }
+}
+
+/// Moves `amount` of a leg out of a signer's wallet (native) or token account.
+fn transfer_from_owner<'info>(
+    leg: &Leg<'_, 'info>,
+    owner: &AccountInfo<'info>,
+    source: Option<&InterfaceAccount<'info, TokenAccount>>,
+    destination: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if leg.is_native() {
+        invoke(
+            &system_instruction::transfer(owner.key, destination.key, amount),
+            &[owner.clone(), destination.clone(), system_program.clone()],
+        )?;
+        return Ok(());
+    }
+
+    let source = source.ok_or(OtcError::MissingLegAccount)?;
+    leg.transfer_checked(&source.to_account_info(), destination, owner, amount, &[])
+}
+
+/// Pays `amount` of an offer's mint_a leg out of escrow. Native escrow is held as lamports on
+/// the offer account, which the program owns and can debit directly.
+fn transfer_from_escrow<'info>(
+    leg_a: &Leg<'_, 'info>,
+    offer: &Account<'info, Offer>,
+    escrow_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    destination: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if leg_a.is_native() {
+        let offer_info = offer.to_account_info();
+        **offer_info.try_borrow_mut_lamports()? = offer_info
+            .lamports()
+            .checked_sub(amount)
+            .ok_or(OtcError::InvalidFillAmount)?;
+        **destination.try_borrow_mut_lamports()? = destination
+            .lamports()
+            .checked_add(amount)
+            .ok_or(OtcError::InvalidFillAmount)?;
+        return Ok(());
+    }
+
+    let escrow_account = escrow_account.ok_or(OtcError::MissingLegAccount)?;
+    let offer_id_bytes = offer.offer_id.to_le_bytes();
+    let seeds = &[b"offer", offer.maker.as_ref(), offer_id_bytes.as_ref(), &[offer.bump]];
+    leg_a.transfer_checked(
+        &escrow_account.to_account_info(),
+        destination,
+        &offer.to_account_info(),
+        amount,
+        &[seeds],
+    )
+}
+
+// ========== State & Events ==========
 
 #[account]
 pub struct Pool {
     pub authority: Pubkey,
-    pub max_partners: u8,
-    pub partners: Vec<Pubkey>,
+    /// Superseded by `max_partners`; kept so existing pool accounts still deserialize.
+    pub legacy_max_partners: u8,
+    /// Partners added before `PartnerRecord`s existed, drained by `migrate_partner`.
+    pub legacy_partners: Vec<Pubkey>,
     pub whitelisted_mints: Vec<Pubkey>,
     pub supported_pairs: Vec<Pair>,
     pub paused: bool,
+    /// Fee on `swap_direct`. Offer fills charge `offer_fees` instead.
     pub fee_bps: u16,
     pub treasury: Pubkey,
     pub min_swap_amount: u64,
     pub max_expiration_secs: i64,
+    pub crank_bounty_lamports: u64,
+    pub swap_fee_policy: SwapFeePolicy,
+    pub cancellation_fee_policy: CancellationFeePolicy,
+    /// Pools created before this field existed read it as all zeros until `resize_pool`
+    /// seeds it.
+    pub offer_fees: OfferFeeSchedule,
+    pub fee_tiers: Vec<FeeTier>,
+    pub tier_window_secs: i64,
+    pub max_partners: u32,
+    pub partner_count: u32,
+    pub pending_authority: Option<Pubkey>,
+    pub pending_treasury: Option<Pubkey>,
+    pub role_grants: Vec<RoleGrant>,
+    /// Minimum time between `queue_change` and its `eta`. While non-zero, timelocked
+    /// parameters can only change through the queue.
+    pub timelock_delay_secs: i64,
+    pub next_change_id: u64,
+    /// `PAUSE_*` bits halting individual operations.
+    pub paused_operations: u8,
+    /// Supported pairs on which trading is halted; refunds still work.
+    pub frozen_pairs: Vec<Pair>,
+    /// Key allowed to call `emergency_pause`.
+    pub guardian: Option<Pubkey>,
+    pub emergency_pause_secs: i64,
+    /// Every operation is halted until this time; 0 when no emergency pause is active.
+    pub emergency_paused_until: i64,
+    pub extension_policy: ExtensionPolicy,
+    /// Set by `emergency_pause` and cleared by `set_guardian`.
+    pub guardian_pause_used: bool,
+    /// `Pool::CONFIG_VERSION` once the pool's settings are initialized; 0 for pools created by
+    /// an older program version until `resize_pool` runs.
+    pub config_version: u8,
 }
 
 impl Pool {
-    pub const MAX_PARTNERS: usize = 255;
     pub const MAX_MINTS: usize = 10;
     pub const MAX_PAIRS: usize = 10;
+    pub const MAX_FEE_TIERS: usize = 8;
+    pub const MAX_ROLE_GRANTS: usize = 16;
+    pub const MAX_TIMELOCK_DELAY_SECS: i64 = 30 * 24 * 60 * 60;
+    pub const MAX_EMERGENCY_PAUSE_SECS: i64 = 7 * 24 * 60 * 60;
+    pub const CONFIG_VERSION: u8 = 1;
     pub const LEN: usize = 8
         + 32
         + 1
-        + 4 + 32 * Self::MAX_PARTNERS
+        + 4
         + 4 + 32 * Self::MAX_MINTS
         + 4 + Pair::LEN * Self::MAX_PAIRS
         + 1
         + 2
         + 32
         + 8
-        + 8;
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct Pair {
-    pub mint_a: Pubkey,
-    pub mint_b: Pubkey,
-}
-
-impl Pair {
-    pub const LEN: usize = 32 + 32;
-}
-
-#[account]
-pub struct Offer {
-    pub maker: Pubkey,
-    pub mint_a: Pubkey,
-    pub mint_b: Pubkey,
-    pub original_amount_a: u64,
-    pub original_amount_b: u64,
-    pub amount_a: u64,
-    pub amount_b: u64,
-    pub expiration_ts: i64,
-    pub fulfilled: bool,
-    pub bump: u8,
-}
-
-impl Offer {
-    pub const LEN: usize = 8
-        + 32
-        + 32
-        + 32
         + 8
         + 8
+        + 1
+        + CancellationFeePolicy::LEN
+        + OfferFeeSchedule::LEN
+        + 4 + FeeTier::LEN * Self::MAX_FEE_TIERS
+        + 8
+        + 4
+        + 4
+        + 1 + 32
+        + 1 + 32
+        + 4 + RoleGrant::LEN * Self::MAX_ROLE_GRANTS
         + 8
         + 8
+        + 1
+        + 4 + Pair::LEN * Self::MAX_PAIRS
+        + 1 + 32
+        + 8
         + 8
+        + ExtensionPolicy::LEN
         + 1
         + 1;
+
+    /// The fee tier a partner currently holds, if any.
+    pub fn fee_tier(&self, stats: &PartnerStats) -> Option<&FeeTier> {
+        (stats.tier as usize).checked_sub(1).and_then(|i| self.fee_tiers.get(i))
+    }
+
+    pub fn config(&self) -> PoolConfig {
+        PoolConfig {
+            fee_bps: self.fee_bps,
+            min_swap_amount: self.min_swap_amount,
+            max_expiration_secs: self.max_expiration_secs,
+            max_partners: self.max_partners,
+        }
+    }
+
+    /// The authority holds every role; anyone else needs an explicit grant.
+    pub fn has_role(&self, member: &Pubkey, role: Role) -> bool {
+        *member == self.authority
+            || self.role_grants.iter().any(|g| g.member == *member && g.role == role)
+    }
+
+    pub fn require_role(&self, member: &Pubkey, role: Role) -> Result<()> {
+        require!(self.has_role(member, role), OtcError::Unauthorized);
+        Ok(())
+    }
+
+    /// Pausing or freezing needs the `Pauser` role; lifting either is reserved to the authority.
+    pub fn require_pause_role(&self, member: &Pubkey, lifting: bool) -> Result<()> {
+        if lifting {
+            require_keys_eq!(self.authority, *member, OtcError::Unauthorized);
+            Ok(())
+        } else {
+            self.require_role(member, Role::Pauser)
+        }
+    }
+
+    pub fn ensure_not_paused(&self, operation: u8) -> Result<()> {
+        require!(!self.paused && self.paused_operations & operation == 0, OtcError::PoolIsPaused);
+        require!(
+            Clock::get()?.unix_timestamp >= self.emergency_paused_until,
+            OtcError::PoolIsPaused
+        );
+        Ok(())
+    }
+
+    pub fn is_pair_frozen(&self, mint_a: Pubkey, mint_b: Pubkey) -> bool {
+        self.frozen_pairs.iter().any(|p| p.mint_a == mint_a && p.mint_b == mint_b)
+    }
+
+    pub fn ensure_pair_active(&self, mint_a: Pubkey, mint_b: Pubkey) -> Result<()> {
+        require!(!self.is_pair_frozen(mint_a, mint_b), OtcError::PairFrozen);
+        Ok(())
+    }
+
+    /// Checks that `member` may queue or cancel `change`.
+    pub fn require_change_role(&self, member: &Pubkey, change: &ParamChange) -> Result<()> {
+        match change.required_role() {
+            Some(role) => self.require_role(member, role),
+            None => {
+                require_keys_eq!(self.authority, *member, OtcError::Unauthorized);
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies a timelocked parameter directly, which is only allowed while the pool has no
+    /// timelock delay.
+    pub fn apply_immediately(&mut self, change: ParamChange) -> Result<()> {
+        require!(self.timelock_delay_secs == 0, OtcError::TimelockActive);
+        change.validate()?;
+        change.apply(self);
+        Ok(())
+    }
 }
 
-#[event]
-pub struct PoolInitialized {
-    pub authority: Pubkey,
-    pub max_partners: u8,
+/// Limits on `extend_offer`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ExtensionPolicy {
+    /// Extensions allowed per offer.
+    pub max_extensions: u8,
+    /// Longest an offer may stay open from creation, counting extensions. 0 for no cap beyond
+    /// `max_expiration_secs`.
+    pub max_offer_lifetime_secs: i64,
+}
+
+impl ExtensionPolicy {
+    pub const LEN: usize = 1 + 8;
+}
+
+/// Snapshot of the settings `update_pool_config` manages, carried by `PoolConfigUpdated`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PoolConfig {
     pub fee_bps: u16,
-    pub treasury: Pubkey,
     pub min_swap_amount: u64,
     pub max_expiration_secs: i64,
+    pub max_partners: u32,
 }
 
-#[event]
-pub struct AuthorityTransferred {
-    pub previous: Pubkey,
-    pub new: Pubkey,
+/// Operational roles delegated by the pool authority.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// May call `pause_pool`; resuming stays with the authority.
+    Pauser,
+    /// Onboards, updates, limits and removes partners.
+    PartnerManager,
+    /// Manages whitelisted mints and supported pairs.
+    ListingManager,
+    /// Sets fee policies, tiers, the crank bounty and rebate vaults, and proposes treasury changes.
+    FeeAdmin,
 }
 
-#[event]
-pub struct TreasuryUpdated {
-    pub previous: Pubkey,
-    pub new: Pubkey,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct RoleGrant {
+    pub member: Pubkey,
+    pub role: Role,
+}
+
+impl RoleGrant {
+    pub const LEN: usize = 32 + 1;
+}
+
+/// M-of-N council that can hold the pool authority, at PDA `[b"admin_council", pool]`.
+#[account]
+pub struct AdminCouncil {
+    pub pool: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,
+    pub proposal_count: u64,
+    pub bump: u8,
+    /// Bump of the signer PDA `[b"council_signer", council]` that executed proposals sign as.
+    pub signer_bump: u8,
+}
+
+impl AdminCouncil {
+    pub const MAX_MEMBERS: usize = 10;
+    pub const LEN: usize = 8 + 32 + 4 + 32 * Self::MAX_MEMBERS + 1 + 8 + 1 + 1;
+
+    pub fn validate_members(members: &[Pubkey], threshold: u8) -> Result<()> {
+        require!(
+            !members.is_empty()
+                && members.len() <= Self::MAX_MEMBERS
+                && threshold >= 1
+                && threshold as usize <= members.len(),
+            OtcError::InvalidCouncilConfig
+        );
+        for (i, member) in members.iter().enumerate() {
+            require!(!members[..i].contains(member), OtcError::InvalidCouncilConfig);
+        }
+        Ok(())
+    }
+
+    /// Approvals from accounts that are still members; removed members no longer count.
+    pub fn approval_count(&self, approvals: &[Pubkey]) -> usize {
+        approvals.iter().filter(|a| self.members.contains(a)).count()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ProposalAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl ProposalAccount {
+    pub const LEN: usize = 32 + 1 + 1;
+}
+
+/// A pending call into this program, at PDA `[b"council_proposal", council, id]`.
+#[account]
+pub struct CouncilProposal {
+    pub council: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub accounts: Vec<ProposalAccount>,
+    pub data: Vec<u8>,
+    pub approvals: Vec<Pubkey>,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl CouncilProposal {
+    pub const MAX_ACCOUNTS: usize = 16;
+    pub const MAX_DATA_LEN: usize = 512;
+    pub const LEN: usize = 8
+        + 32
+        + 8
+        + 32
+        + 4 + ProposalAccount::LEN * Self::MAX_ACCOUNTS
+        + 4 + Self::MAX_DATA_LEN
+        + 4 + 32 * AdminCouncil::MAX_MEMBERS
+        + 1
+        + 1;
+}
+
+/// A pool parameter change that is subject to the timelock.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum ParamChange {
+    /// The `swap_direct` fee; offer fees are changed through `OfferFees`.
+    FeeBps(u16),
+    SwapFeePolicy(SwapFeePolicy),
+    CancellationFeePolicy(CancellationFeePolicy),
+    OfferFees(OfferFeeSchedule),
+    FeeTiers { tiers: Vec<FeeTier>, tier_window_secs: i64 },
+    /// Executing opens a treasury proposal; the new treasury still signs `accept_treasury`.
+    Treasury(Pubkey),
+    MinSwapAmount(u64),
+    MaxExpirationSecs(i64),
+    TimelockDelay(i64),
+}
+
+impl ParamChange {
+    /// Largest variant (`FeeTiers`) plus the enum tag.
+    pub const LEN: usize = 1 + 4 + FeeTier::LEN * Pool::MAX_FEE_TIERS + 8;
+
+    /// Role allowed to queue or cancel the change; `None` means the authority only.
+    pub fn required_role(&self) -> Option<Role> {
+        match self {
+            ParamChange::MinSwapAmount(_)
+            | ParamChange::MaxExpirationSecs(_)
+            | ParamChange::TimelockDelay(_) => None,
+            _ => Some(Role::FeeAdmin),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ParamChange::FeeBps(bps) => require!(*bps <= 10_000, OtcError::InvalidFeeConfig),
+            ParamChange::CancellationFeePolicy(policy) => policy.validate()?,
+            ParamChange::OfferFees(fees) => fees.validate()?,
+            ParamChange::FeeTiers { tiers, tier_window_secs } => {
+                require!(
+                    tiers.len() <= Pool::MAX_FEE_TIERS && *tier_window_secs > 0,
+                    OtcError::InvalidFeeConfig
+                );
+                for tier in tiers {
+                    require!(
+                        tier.maker_fee_bps.unsigned_abs() <= 10_000 && tier.taker_fee_bps <= 10_000,
+                        OtcError::InvalidFeeConfig
+                    );
+                }
+            }
+            ParamChange::MaxExpirationSecs(secs) => require!(
+                *secs >= MIN_OFFER_LIFETIME_SECS,
+                OtcError::InvalidPoolConfig
+            ),
+            ParamChange::TimelockDelay(secs) => require!(
+                (0..=Pool::MAX_TIMELOCK_DELAY_SECS).contains(secs),
+                OtcError::InvalidTimelockDelay
+            ),
+            ParamChange::SwapFeePolicy(_) | ParamChange::Treasury(_) | ParamChange::MinSwapAmount(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Writes the change to the pool, emitting the same event as the direct setter.
+    fn apply(self, pool: &mut Pool) {
+        match self {
+            ParamChange::FeeBps(bps) => {
+                let previous = pool.config();
+                pool.fee_bps = bps;
+                emit!(PoolConfigUpdated { previous, new: pool.config() });
+            }
+            ParamChange::SwapFeePolicy(policy) => {
+                let previous = pool.swap_fee_policy;
+                pool.swap_fee_policy = policy;
+                emit!(SwapFeePolicyUpdated { previous, new: policy });
+            }
+            ParamChange::CancellationFeePolicy(policy) => {
+                let previous = pool.cancellation_fee_policy;
+                pool.cancellation_fee_policy = policy;
+                emit!(CancellationFeePolicyUpdated { previous, new: policy });
+            }
+            ParamChange::OfferFees(fees) => {
+                let previous = pool.offer_fees;
+                pool.offer_fees = fees;
+                emit!(OfferFeesUpdated { previous, new: fees });
+            }
+            ParamChange::FeeTiers { tiers, tier_window_secs } => {
+                pool.fee_tiers = tiers.clone();
+                pool.tier_window_secs = tier_window_secs;
+                emit!(FeeTiersUpdated { tiers, tier_window_secs });
+            }
+            ParamChange::Treasury(new_treasury) => {
+                pool.pending_treasury = Some(new_treasury);
+                emit!(TreasuryProposed { current: pool.treasury, proposed: new_treasury });
+            }
+            ParamChange::MinSwapAmount(amount) => {
+                let previous = pool.config();
+                pool.min_swap_amount = amount;
+                emit!(PoolConfigUpdated { previous, new: pool.config() });
+            }
+            ParamChange::MaxExpirationSecs(secs) => {
+                let previous = pool.config();
+                pool.max_expiration_secs = secs;
+                emit!(PoolConfigUpdated { previous, new: pool.config() });
+            }
+            ParamChange::TimelockDelay(secs) => {
+                let previous = pool.timelock_delay_secs;
+                pool.timelock_delay_secs = secs;
+                emit!(TimelockDelayUpdated { previous, new: secs });
+            }
+        }
+    }
+}
+
+/// A queued `ParamChange`, at PDA `[b"queued_change", pool, id]`. Closed to the proposer when
+/// executed or cancelled.
+#[account]
+pub struct QueuedChange {
+    pub pool: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub change: ParamChange,
+    pub eta: i64,
+    pub bump: u8,
+}
+
+impl QueuedChange {
+    pub const LEN: usize = 8 + 32 + 8 + 32 + ParamChange::LEN + 8 + 1;
+}
+
+/// Which side of a `swap_direct` pays the pool's `fee_bps`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SwapFeePolicy {
+    /// `fee_bps` is taken from `amount_a` only.
+    SideA,
+    /// `fee_bps` is taken from `amount_b` only.
+    SideB,
+    /// `fee_bps` is taken from both legs.
+    BothSides,
+    /// Half of `fee_bps` is taken from each leg.
+    Split,
+}
+
+impl SwapFeePolicy {
+    /// Returns the fees charged on leg A and leg B, each in that leg's mint. `fee_bps_a` and
+    /// `fee_bps_b` are the rates of the parties sending each leg, which differ once partners
+    /// hold fee tiers.
+    pub fn split_fees(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        fee_bps_a: u16,
+        fee_bps_b: u16,
+    ) -> Result<(u64, u64)> {
+        Ok(match self {
+            SwapFeePolicy::SideA => (bps_fee(amount_a, fee_bps_a)?, 0),
+            SwapFeePolicy::SideB => (0, bps_fee(amount_b, fee_bps_b)?),
+            SwapFeePolicy::BothSides => (bps_fee(amount_a, fee_bps_a)?, bps_fee(amount_b, fee_bps_b)?),
+            SwapFeePolicy::Split => (
+                bps_fee(amount_a, fee_bps_a / 2)?,
+                bps_fee(amount_b, fee_bps_b - fee_bps_b / 2)?,
+            ),
+        })
+    }
+}
+
+/// Fee charged in mint_a when an offer is cancelled or closed after expiry.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CancellationFeePolicy {
+    None,
+    /// A fixed amount of mint_a, capped at the remaining escrow.
+    Flat { amount: u64 },
+    /// `bps` of the remaining escrow.
+    Bps { bps: u16 },
+    /// `bps` of the remaining escrow, charged only once the offer has rested for at least
+    /// `min_resting_secs`; pulling an offer sooner, e.g. to fix a mistake, is free.
+    AfterRestingTime { bps: u16, min_resting_secs: i64 },
+}
+
+impl CancellationFeePolicy {
+    /// Largest variant (`AfterRestingTime`) plus the enum tag.
+    pub const LEN: usize = 1 + 2 + 8;
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            CancellationFeePolicy::Bps { bps } => require!(*bps <= 10_000, OtcError::InvalidFeeConfig),
+            CancellationFeePolicy::AfterRestingTime { bps, min_resting_secs } => require!(
+                *bps <= 10_000 && *min_resting_secs >= 0,
+                OtcError::InvalidFeeConfig
+            ),
+            CancellationFeePolicy::None | CancellationFeePolicy::Flat { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Fee owed on `amount` of remaining escrow for an offer that has been open `resting_secs`.
+    pub fn fee(&self, amount: u64, resting_secs: i64) -> Result<u64> {
+        Ok(match *self {
+            CancellationFeePolicy::None => 0,
+            CancellationFeePolicy::Flat { amount: flat } => flat.min(amount),
+            CancellationFeePolicy::Bps { bps } => bps_fee(amount, bps)?,
+            CancellationFeePolicy::AfterRestingTime { bps, min_resting_secs } => {
+                if resting_secs >= min_resting_secs {
+                    bps_fee(amount, bps)?
+                } else {
+                    0
+                }
+            }
+        })
+    }
+}
+
+/// Fees charged on `accept_offer` fills. A negative `maker_fee_bps` is a rebate paid in mint_b
+/// from the pool's rebate vault, which the treasury funds out of collected fees. Only the taker
+/// fee has a currency: the maker fee is always withheld from the mint_b the maker receives,
+/// because the maker does not sign fills and every escrowed mint_a is owed to takers.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct OfferFeeSchedule {
+    pub maker_fee_bps: i16,
+    pub taker_fee_bps: u16,
+    pub taker_fee_currency: FeeCurrency,
+}
+
+impl OfferFeeSchedule {
+    pub const LEN: usize = 2 + 2 + 1;
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.maker_fee_bps.unsigned_abs() <= 10_000 && self.taker_fee_bps <= 10_000,
+            OtcError::InvalidFeeConfig
+        );
+        Ok(())
+    }
+}
+
+/// Mint a fee is charged in.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FeeCurrency {
+    MintA,
+    MintB,
+}
+
+/// Marks `partner` as whitelisted in `pool`, at PDA `[b"partner", pool, partner]`. Trading
+/// instructions require the record by address, so membership checks are constant time.
+#[account]
+pub struct PartnerRecord {
+    pub pool: Pubkey,
+    pub partner: Pubkey,
+    pub bump: u8,
+    /// Compliance tier assigned during onboarding.
+    pub kyc_tier: u8,
+    pub onboarded_ts: i64,
+    /// Trading is rejected after this time.
+    pub expiry_ts: i64,
+    pub status: PartnerStatus,
+    /// Hash of the partner's off-chain KYC file.
+    pub reference_hash: [u8; 32],
+    /// Blocks every operation for the partner, including cancels and extensions.
+    pub frozen: bool,
+}
+
+impl PartnerRecord {
+    pub const LEN: usize = 8
+        + 32
+        + 32
+        + 1
+        + 1
+        + 8
+        + 8
+        + 1
+        + 32
+        + 1;
+
+    pub fn ensure_can_trade(&self, now: i64) -> Result<()> {
+        require!(!self.frozen, OtcError::PartnerFrozen);
+        match self.status {
+            PartnerStatus::Active => {}
+            PartnerStatus::Suspended => return err!(OtcError::PartnerSuspended),
+            PartnerStatus::Offboarded => return err!(OtcError::PartnerOffboarded),
+        }
+        require!(now <= self.expiry_ts, OtcError::PartnerExpired);
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PartnerStatus {
+    Active,
+    Suspended,
+    Offboarded,
+}
+
+/// Caps on what a partner may do in one mint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct MintLimits {
+    /// Largest amount sent in one swap or fill, or escrowed in one offer.
+    pub max_single_trade: u64,
+    /// Largest amount sent over any rolling 24 hours.
+    pub max_daily_volume: u64,
+    /// Largest total escrowed across the partner's open offers.
+    pub max_open_escrow: u64,
+}
+
+/// A partner's limits and usage for one mint, at PDA `[b"partner_limit", pool, partner, mint]`.
+/// Volume is counted on the mint the partner sends.
+#[account]
+pub struct PartnerLimit {
+    pub pool: Pubkey,
+    pub partner: Pubkey,
+    pub mint: Pubkey,
+    pub limits: MintLimits,
+    /// Unix hour of the most recent bucket in `hourly_volume`.
+    pub last_hour: i64,
+    pub hourly_volume: [u64; LIMIT_WINDOW_HOURS],
+    pub open_escrow: u64,
+    pub bump: u8,
+}
+
+impl PartnerLimit {
+    pub const LEN: usize = 8
+        + 32
+        + 32
+        + 32
+        + 8 * 3
+        + 8
+        + 8 * LIMIT_WINDOW_HOURS
+        + 8
+        + 1;
+
+    pub fn check_single_trade(&self, amount: u64) -> Result<()> {
+        require!(amount <= self.limits.max_single_trade, OtcError::TradeAboveLimit);
+        Ok(())
+    }
+
+    /// Adds `amount` to the rolling 24h volume, clearing buckets for hours that have passed.
+    pub fn record_volume(&mut self, amount: u64, now: i64) -> Result<()> {
+        let hour = now / 3600;
+        let elapsed = hour.saturating_sub(self.last_hour).min(LIMIT_WINDOW_HOURS as i64);
+        for h in (hour - elapsed + 1)..=hour {
+            self.hourly_volume[h.rem_euclid(LIMIT_WINDOW_HOURS as i64) as usize] = 0;
+        }
+        self.last_hour = self.last_hour.max(hour);
+
+        let volume = self
+            .hourly_volume
+            .iter()
+            .try_fold(amount, |acc, v| acc.checked_add(*v))
+            .ok_or(OtcError::MathOverflow)?;
+        require!(volume <= self.limits.max_daily_volume, OtcError::DailyVolumeLimitExceeded);
+        self.hourly_volume[hour.rem_euclid(LIMIT_WINDOW_HOURS as i64) as usize] += amount;
+        Ok(())
+    }
+
+    pub fn record_trade(&mut self, amount: u64, now: i64) -> Result<()> {
+        self.check_single_trade(amount)?;
+        self.record_volume(amount, now)
+    }
+
+    pub fn add_open_escrow(&mut self, amount: u64) -> Result<()> {
+        let open_escrow = self.open_escrow.checked_add(amount).ok_or(OtcError::MathOverflow)?;
+        require!(open_escrow <= self.limits.max_open_escrow, OtcError::OpenEscrowLimitExceeded);
+        self.open_escrow = open_escrow;
+        Ok(())
+    }
+
+    pub fn release_open_escrow(&mut self, amount: u64) {
+        self.open_escrow = self.open_escrow.saturating_sub(amount);
+    }
+}
+
+/// A fee tier: partners whose window volume in `mint` reaches `min_volume` trade at these
+/// rates. Later entries in the pool's table take precedence.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeTier {
+    pub mint: Pubkey,
+    pub min_volume: u64,
+    pub maker_fee_bps: i16,
+    /// Also the partner's rate on `swap_direct`.
+    pub taker_fee_bps: u16,
+}
+
+impl FeeTier {
+    pub const LEN: usize = 32 + 8 + 2 + 2;
+}
+
+/// Per-partner trading volume and fee tier, at PDA `[b"partner_stats", pool, partner]`.
+#[account]
+pub struct PartnerStats {
+    pub pool: Pubkey,
+    pub partner: Pubkey,
+    /// 1-based index into `Pool.fee_tiers`; 0 means the pool's default fees.
+    pub tier: u8,
+    pub window_start_ts: i64,
+    pub volumes: Vec<MintVolume>,
+    pub bump: u8,
+}
+
+impl PartnerStats {
+    pub const MAX_MINTS: usize = Pool::MAX_MINTS;
+    pub const LEN: usize = 8
+        + 32
+        + 32
+        + 1
+        + 8
+        + 4 + MintVolume::LEN * Self::MAX_MINTS
+        + 1;
+
+    pub fn window_volume(&self, mint: &Pubkey) -> u64 {
+        self.volumes
+            .iter()
+            .find(|v| v.mint == *mint)
+            .map_or(0, |v| v.window_volume)
+    }
+
+    /// Window volume in `mint` scaled to a single window of `window_secs`. A window that ran
+    /// longer because nobody recomputed the tier counts at its average rate, so volume cannot
+    /// pile up across several windows.
+    pub fn volume_per_window(&self, mint: &Pubkey, now: i64, window_secs: i64) -> u64 {
+        let volume = self.window_volume(mint);
+        let elapsed = now.saturating_sub(self.window_start_ts);
+        if window_secs <= 0 || elapsed <= window_secs {
+            return volume;
+        }
+        (volume as u128 * window_secs as u128 / elapsed as u128) as u64
+    }
+
+    /// Adds `amount` of `mint` to the cumulative and window totals. When every slot is taken,
+    /// entries for mints the pool no longer whitelists are dropped to make room.
+    pub fn record_volume(&mut self, mint: Pubkey, amount: u64, whitelisted_mints: &[Pubkey]) -> Result<()> {
+        if let Some(volume) = self.volumes.iter_mut().find(|v| v.mint == mint) {
+            volume.cumulative_volume = volume.cumulative_volume.saturating_add(amount);
+            volume.window_volume = volume.window_volume.saturating_add(amount);
+            return Ok(());
+        }
+        if self.volumes.len() >= Self::MAX_MINTS {
+            self.volumes.retain(|v| whitelisted_mints.contains(&v.mint));
+        }
+        require!(self.volumes.len() < Self::MAX_MINTS, OtcError::PartnerStatsFull);
+        self.volumes.push(MintVolume {
+            mint,
+            cumulative_volume: amount,
+            window_volume: amount,
+        });
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MintVolume {
+    pub mint: Pubkey,
+    pub cumulative_volume: u64,
+    pub window_volume: u64,
+}
+
+impl MintVolume {
+    pub const LEN: usize = 32 + 8 + 8;
+}
+
+/// Holds lamports for native SOL maker rebates. SPL rebate vaults are plain token accounts at
+/// the same seeds, owned by the pool's rebate authority PDA.
+#[account]
+pub struct NativeRebateVault {
+    pub pool: Pubkey,
+    pub bump: u8,
+}
+
+impl NativeRebateVault {
+    pub const LEN: usize = 8 + 32 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Pair {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+}
+
+impl Pair {
+    pub const LEN: usize = 32 + 32;
+}
+
+#[account]
+pub struct Offer {
+    pub pool: Pubkey,
+    pub maker: Pubkey,
+    pub offer_id: u64,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    /// Terms the fill price is derived from; reset by `amend_offer`.
+    pub original_amount_a: u64,
+    pub original_amount_b: u64,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub expiration_ts: i64,
+    pub fulfilled: bool,
+    pub bump: u8,
+    pub escrow_bump: u8,
+    pub decimals_a: u8,
+    pub decimals_b: u8,
+    pub created_ts: i64,
+    /// Counterparties allowed to fill a private offer; empty for a public offer.
+    pub allowed_takers: Vec<Pubkey>,
+    pub fill_constraints: FillConstraints,
+    pub fill_count: u16,
+    /// Bumped whenever the maker changes the offer's terms; takers pass the value they priced
+    /// against to `accept_offer`.
+    pub sequence: u64,
+    pub extension_count: u8,
+    /// Lamports taken from the maker at creation and paid to whoever cranks the offer after
+    /// it expires.
+    pub crank_deposit: u64,
+}
+
+impl Offer {
+    pub const MAX_ALLOWED_TAKERS: usize = 4;
+    pub const LEN: usize = 8
+        + 32
+        + 32
+        + 8
+        + 32
+        + 32
+        + 32
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 1
+        + 1
+        + 1
+        + 1
+        + 1
+        + 8
+        + 4 + 32 * Self::MAX_ALLOWED_TAKERS
+        + FillConstraints::LEN
+        + 2
+        + 8
+        + 1
+        + 8;
+
+    /// Checks a fill of `take_b` against the offer's fill constraints. A fill that takes the
+    /// whole remainder is always allowed past the minimum fill size.
+    pub fn check_fill(&self, take_b: u64) -> Result<()> {
+        let constraints = &self.fill_constraints;
+        let completes = take_b == self.amount_b;
+        require!(!constraints.all_or_none || completes, OtcError::AllOrNoneFill);
+        require!(completes || take_b >= constraints.min_fill_b, OtcError::FillBelowMinimum);
+        require!(
+            completes || self.amount_b - take_b >= constraints.min_fill_b,
+            OtcError::RemainderBelowMinimum
+        );
+        require!(
+            completes
+                || constraints.max_fills == 0
+                || self.fill_count.saturating_add(1) < constraints.max_fills,
+            OtcError::MaxFillsReached
+        );
+        Ok(())
+    }
+}
+
+/// Per-offer limits on how `accept_offer` may split the offer.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct FillConstraints {
+    /// The offer can only be filled in a single `accept_offer` taking all of `amount_b`.
+    pub all_or_none: bool,
+    /// Smallest fill in mint_b, also the smallest remainder a partial fill may leave. 0 for none.
+    pub min_fill_b: u64,
+    /// Most fills the offer accepts; the last one must take the whole remainder. 0 for no limit.
+    pub max_fills: u16,
+}
+
+impl FillConstraints {
+    pub const LEN: usize = 1 + 8 + 2;
+}
+
+#[event]
+pub struct PoolInitialized {
+    pub authority: Pubkey,
+    pub max_partners: u32,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    pub min_swap_amount: u64,
+    pub max_expiration_secs: i64,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub current: Pubkey,
+    pub proposed: Pubkey,
+}
+
+#[event]
+pub struct AuthorityProposalCancelled {
+    pub cancelled: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous: Pubkey,
+    pub new: Pubkey,
+}
+
+#[event]
+pub struct TreasuryProposed {
+    pub current: Pubkey,
+    pub proposed: Pubkey,
+}
+
+#[event]
+pub struct TreasuryProposalCancelled {
+    pub cancelled: Pubkey,
+}
+
+#[event]
+pub struct ExtensionPolicyUpdated {
+    pub previous: ExtensionPolicy,
+    pub new: ExtensionPolicy,
+}
+
+#[event]
+pub struct PoolConfigUpdated {
+    pub previous: PoolConfig,
+    pub new: PoolConfig,
+}
+
+#[event]
+pub struct TimelockDelayUpdated {
+    pub previous: i64,
+    pub new: i64,
+}
+
+#[event]
+pub struct ChangeQueued {
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub change: ParamChange,
+    pub eta: i64,
+}
+
+#[event]
+pub struct ChangeCancelled {
+    pub id: u64,
+    pub change: ParamChange,
+}
+
+#[event]
+pub struct ChangeExecuted {
+    pub id: u64,
+    pub change: ParamChange,
+}
+
+#[event]
+pub struct AdminCouncilInitialized {
+    pub council: Pubkey,
+    pub signer: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct AdminCouncilUpdated {
+    pub council: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct CouncilProposalCreated {
+    pub council: Pubkey,
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+}
+
+#[event]
+pub struct CouncilProposalApproved {
+    pub proposal: Pubkey,
+    pub member: Pubkey,
+    pub approvals: u8,
+}
+
+#[event]
+pub struct CouncilProposalExecuted {
+    pub proposal: Pubkey,
+    pub id: u64,
+}
+
+#[event]
+pub struct RoleGranted {
+    pub member: Pubkey,
+    pub role: Role,
+}
+
+#[event]
+pub struct RoleRevoked {
+    pub member: Pubkey,
+    pub role: Role,
+}
+
+#[event]
+pub struct TreasuryUpdated {
+    pub previous: Pubkey,
+    pub new: Pubkey,
 }
 
 #[event]
@@ -558,12 +3145,97 @@ pub struct MintRemoved {
 #[event]
 pub struct OfferExpired {
     pub maker: Pubkey,
+    pub offer_id: u64,
     pub expiration_ts: i64,
+    pub refunded_amount_a: u64,
+    pub cancellation_fee: u64,
+}
+
+#[event]
+pub struct ExpiredOffersCranked {
+    pub keeper: Pubkey,
+    pub closed: u64,
+    pub bounty_paid: u64,
+}
+
+#[event]
+pub struct CrankBountyUpdated {
+    pub previous: u64,
+    pub new: u64,
+}
+
+#[event]
+pub struct SwapFeePolicyUpdated {
+    pub previous: SwapFeePolicy,
+    pub new: SwapFeePolicy,
+}
+
+#[event]
+pub struct CancellationFeePolicyUpdated {
+    pub previous: CancellationFeePolicy,
+    pub new: CancellationFeePolicy,
+}
+
+#[event]
+pub struct OfferFeesUpdated {
+    pub previous: OfferFeeSchedule,
+    pub new: OfferFeeSchedule,
+}
+
+#[event]
+pub struct FeeTiersUpdated {
+    pub tiers: Vec<FeeTier>,
+    pub tier_window_secs: i64,
+}
+
+#[event]
+pub struct PartnerStatsInitialized {
+    pub partner: Pubkey,
+}
+
+#[event]
+pub struct PartnerTierUpdated {
+    pub partner: Pubkey,
+    pub previous_tier: u8,
+    pub new_tier: u8,
+}
+
+#[event]
+pub struct RebateVaultInitialized {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
 }
 
 #[event]
 pub struct PartnerAdded {
     pub partner: Pubkey,
+    pub kyc_tier: u8,
+    pub onboarded_ts: i64,
+    pub expiry_ts: i64,
+    pub reference_hash: [u8; 32],
+}
+
+#[event]
+pub struct PartnerMetadataUpdated {
+    pub partner: Pubkey,
+    pub kyc_tier: u8,
+    pub expiry_ts: i64,
+    pub reference_hash: [u8; 32],
+}
+
+#[event]
+pub struct PartnerLimitSet {
+    pub partner: Pubkey,
+    pub mint: Pubkey,
+    pub previous: Option<MintLimits>,
+    pub new: MintLimits,
+}
+
+#[event]
+pub struct PartnerStatusChanged {
+    pub partner: Pubkey,
+    pub previous: PartnerStatus,
+    pub new: PartnerStatus,
 }
 
 #[event]
@@ -571,6 +3243,11 @@ pub struct PartnerRemoved {
     pub partner: Pubkey,
 }
 
+#[event]
+pub struct PartnerMigrated {
+    pub partner: Pubkey,
+}
+
 #[event]
 pub struct SupportedPairAdded {
     pub mint_a: Pubkey,
@@ -583,6 +3260,49 @@ pub struct SupportedPairRemoved {
     pub mint_b: Pubkey,
 }
 
+#[event]
+pub struct PausedOperationsUpdated {
+    pub admin: Pubkey,
+    pub previous: u8,
+    pub new: u8,
+}
+
+#[event]
+pub struct PairFreezeUpdated {
+    pub admin: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub frozen: bool,
+}
+
+#[event]
+pub struct PartnerFreezeUpdated {
+    pub admin: Pubkey,
+    pub partner: Pubkey,
+    pub frozen: bool,
+}
+
+#[event]
+pub struct GuardianUpdated {
+    pub previous: Option<Pubkey>,
+    pub new: Option<Pubkey>,
+    pub emergency_pause_secs: i64,
+}
+
+#[event]
+pub struct EmergencyPaused {
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+    /// The pause lapses at this time unless the authority confirms it.
+    pub expires_ts: i64,
+}
+
+#[event]
+pub struct EmergencyPauseConfirmed {
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct PoolPaused {
     pub admin: Pubkey,
@@ -605,26 +3325,38 @@ pub struct SwapDirectExecuted {
     pub filled_amount_b: u64,
     pub remaining_amount_a: u64,
     pub remaining_amount_b: u64,
+    pub fee_a: u64,
+    pub fee_b: u64,
 }
 
 #[event]
 pub struct OfferCreated {
     pub maker: Pubkey,
+    pub offer_id: u64,
     pub mint_a: Pubkey,
     pub mint_b: Pubkey,
     pub amount_a: u64,
     pub amount_b: u64,
     pub expiration_ts: i64,
+    pub decimals_a: u8,
+    pub decimals_b: u8,
+    /// Non-empty for private offers that only these takers can fill.
+    pub allowed_takers: Vec<Pubkey>,
+    pub fill_constraints: FillConstraints,
 }
 
 #[event]
 pub struct OfferCancelled {
     pub maker: Pubkey,
+    pub offer_id: u64,
+    pub refunded_amount_a: u64,
+    pub cancellation_fee: u64,
 }
 
 #[event]
 pub struct OfferExecuted {
     pub maker: Pubkey,
+    pub offer_id: u64,
     pub taker: Pubkey,
     pub mint_a: Pubkey,
     pub mint_b: Pubkey,
@@ -632,85 +3364,406 @@ pub struct OfferExecuted {
     pub filled_amount_b: u64,
     pub remaining_amount_a: u64,
     pub remaining_amount_b: u64,
+    /// Fills executed on the offer so far, including this one.
+    pub fill_count: u16,
+    /// Maker fee withheld from the mint_b paid to the maker.
+    pub maker_fee: u64,
+    /// Rebate paid to the maker in mint_b when `maker_fee_bps` is negative.
+    pub maker_rebate: u64,
+    pub taker_fee: u64,
+    pub taker_fee_mint: Pubkey,
+}
+
+#[event]
+pub struct OfferAmended {
+    pub maker: Pubkey,
+    pub offer_id: u64,
+    pub old_amount_a: u64,
+    pub old_amount_b: u64,
+    pub new_amount_a: u64,
+    pub new_amount_b: u64,
+    pub sequence: u64,
 }
 
 #[event]
 pub struct OfferExtended {
     pub maker: Pubkey,
+    pub offer_id: u64,
     pub new_expiration_ts: i64,
+    /// Extensions used so far, including this one.
+    pub extension_count: u8,
+}
+
+// ========== Accounts Contexts ==========
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(init, payer = authority, space = Pool::LEN)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
-/// ========== Accounts Contexts ==========
+#[derive(Accounts)]
+pub struct TransferAuthority<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptTreasury<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    pub new_treasury: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitAdminCouncil<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = AdminCouncil::LEN,
+        seeds = [b"admin_council", pool.key().as_ref()],
+        bump
+    )]
+    pub council: Account<'info, AdminCouncil>,
+    /// CHECK: signer PDA for executed proposals; holds no data
+    #[account(seeds = [b"council_signer", council.key().as_ref()], bump)]
+    pub council_signer: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAdminCouncil<'info> {
+    #[account(mut, seeds = [b"admin_council", council.pool.as_ref()], bump = council.bump)]
+    pub council: Account<'info, AdminCouncil>,
+    #[account(seeds = [b"council_signer", council.key().as_ref()], bump = council.signer_bump)]
+    pub council_signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateCouncilProposal<'info> {
+    #[account(mut, seeds = [b"admin_council", council.pool.as_ref()], bump = council.bump)]
+    pub council: Account<'info, AdminCouncil>,
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    #[account(
+        init,
+        payer = proposer,
+        space = CouncilProposal::LEN,
+        seeds = [b"council_proposal", council.key().as_ref(), council.proposal_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, CouncilProposal>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveCouncilProposal<'info> {
+    #[account(seeds = [b"admin_council", council.pool.as_ref()], bump = council.bump)]
+    pub council: Account<'info, AdminCouncil>,
+    pub member: Signer<'info>,
+    #[account(
+        mut,
+        has_one = council,
+        seeds = [b"council_proposal", council.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, CouncilProposal>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteCouncilProposal<'info> {
+    #[account(seeds = [b"admin_council", council.pool.as_ref()], bump = council.bump)]
+    pub council: Account<'info, AdminCouncil>,
+    pub executor: Signer<'info>,
+    #[account(
+        mut,
+        has_one = council,
+        seeds = [b"council_proposal", council.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, CouncilProposal>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyPoolMints<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(partner: Pubkey)]
+pub struct AddPartner<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = PartnerRecord::LEN,
+        seeds = [b"partner", pool.key().as_ref(), partner.as_ref()],
+        bump
+    )]
+    pub partner_record: Account<'info, PartnerRecord>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResizePool<'info> {
+    /// CHECK: a pool that may be too short to deserialize; `resize_pool` checks its
+    /// discriminator and authority by hand.
+    #[account(mut, owner = crate::ID)]
+    pub pool: AccountInfo<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyPartnerRecord<'info> {
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = pool,
+        seeds = [b"partner", pool.key().as_ref(), partner_record.partner.as_ref()],
+        bump = partner_record.bump
+    )]
+    pub partner_record: Account<'info, PartnerRecord>,
+}
+
+#[derive(Accounts)]
+#[instruction(partner: Pubkey, mint: Pubkey)]
+pub struct InitPartnerLimit<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"partner", pool.key().as_ref(), partner.as_ref()], bump = partner_record.bump)]
+    pub partner_record: Account<'info, PartnerRecord>,
+    #[account(
+        init,
+        payer = authority,
+        space = PartnerLimit::LEN,
+        seeds = [b"partner_limit", pool.key().as_ref(), partner.as_ref(), mint.as_ref()],
+        bump
+    )]
+    pub partner_limit: Account<'info, PartnerLimit>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPartnerLimit<'info> {
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = pool,
+        seeds = [
+            b"partner_limit",
+            pool.key().as_ref(),
+            partner_limit.partner.as_ref(),
+            partner_limit.mint.as_ref()
+        ],
+        bump = partner_limit.bump
+    )]
+    pub partner_limit: Account<'info, PartnerLimit>,
+}
 
 #[derive(Accounts)]
-pub struct InitializePool<'info> {
-    #[account(init, payer = authority, space = Pool::LEN)]
+pub struct RemovePartner<'info> {
+    #[account(mut)]
     pub pool: Account<'info, Pool>,
     #[account(mut)]
     pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+    #[account(
+        mut,
+        has_one = pool,
+        close = authority,
+        seeds = [b"partner", pool.key().as_ref(), partner_record.partner.as_ref()],
+        bump = partner_record.bump
+    )]
+    pub partner_record: Account<'info, PartnerRecord>,
 }
 
 #[derive(Accounts)]
-pub struct TransferAuthority<'info> {
-    #[account(mut, has_one = authority)]
+pub struct ModifySupportedPair<'info> {
+    #[account(mut)]
     pub pool: Account<'info, Pool>,
     pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ModifyPoolMints<'info> {
-    #[account(mut, has_one = authority)]
+pub struct ModifyPoolState<'info> {
+    #[account(mut)]
     pub pool: Account<'info, Pool>,
     pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ModifyPartner<'info> {
-    #[account(mut, has_one = authority)]
+pub struct EmergencyPause<'info> {
+    #[account(mut)]
     pub pool: Account<'info, Pool>,
-    pub authority: Signer<'info>,
+    pub guardian: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ModifySupportedPair<'info> {
-    #[account(mut, has_one = authority)]
+pub struct QueueChange<'info> {
+    #[account(mut)]
     pub pool: Account<'info, Pool>,
-    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    #[account(
+        init,
+        payer = proposer,
+        space = QueuedChange::LEN,
+        seeds = [b"queued_change", pool.key().as_ref(), pool.next_change_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub queued_change: Account<'info, QueuedChange>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ModifyPoolState<'info> {
-    #[account(mut, has_one = authority)]
+pub struct CancelChange<'info> {
     pub pool: Account<'info, Pool>,
     pub authority: Signer<'info>,
+    /// CHECK: receives the queued change's rent
+    #[account(mut, address = queued_change.proposer)]
+    pub proposer: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = pool,
+        close = proposer,
+        seeds = [b"queued_change", pool.key().as_ref(), queued_change.id.to_le_bytes().as_ref()],
+        bump = queued_change.bump
+    )]
+    pub queued_change: Account<'info, QueuedChange>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteChange<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: receives the queued change's rent
+    #[account(mut, address = queued_change.proposer)]
+    pub proposer: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = pool,
+        close = proposer,
+        seeds = [b"queued_change", pool.key().as_ref(), queued_change.id.to_le_bytes().as_ref()],
+        bump = queued_change.bump
+    )]
+    pub queued_change: Account<'info, QueuedChange>,
 }
 
 #[derive(Accounts)]
 pub struct SwapDirect<'info> {
     pub pool: Account<'info, Pool>,
 
-    #[account(signer)]
+    #[account(mut, signer)]
     pub party_a: AccountInfo<'info>,
-    #[account(signer)]
+    #[account(mut, signer)]
     pub party_b: AccountInfo<'info>,
 
-    #[account(mut, token::authority = party_a)]
-    pub party_a_token_src: Account<'info, TokenAccount>,
-    #[account(mut, token::authority = party_b)]
-    pub party_b_token_dest: Account<'info, TokenAccount>,
+    #[account(
+        seeds = [b"partner", pool.key().as_ref(), party_a.key().as_ref()],
+        bump = party_a_record.bump
+    )]
+    pub party_a_record: Box<Account<'info, PartnerRecord>>,
+    #[account(
+        seeds = [b"partner", pool.key().as_ref(), party_b.key().as_ref()],
+        bump = party_b_record.bump
+    )]
+    pub party_b_record: Box<Account<'info, PartnerRecord>>,
+
+    /// CHECK: party_a's `PartnerStats`; uninitialized until `init_partner_stats` is called
+    #[account(
+        mut,
+        seeds = [b"partner_stats", pool.key().as_ref(), party_a.key().as_ref()],
+        bump
+    )]
+    pub party_a_stats: AccountInfo<'info>,
+    /// CHECK: party_b's `PartnerStats`; uninitialized until `init_partner_stats` is called
+    #[account(
+        mut,
+        seeds = [b"partner_stats", pool.key().as_ref(), party_b.key().as_ref()],
+        bump
+    )]
+    pub party_b_stats: AccountInfo<'info>,
+
+    /// CHECK: party_a's `PartnerLimit` for mint_a; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [
+            b"partner_limit",
+            pool.key().as_ref(),
+            party_a.key().as_ref(),
+            mint_a.as_ref().map_or(NATIVE_MINT, |m| m.key()).as_ref()
+        ],
+        bump
+    )]
+    pub party_a_limit: AccountInfo<'info>,
+    /// CHECK: party_b's `PartnerLimit` for mint_b; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [
+            b"partner_limit",
+            pool.key().as_ref(),
+            party_b.key().as_ref(),
+            mint_b.as_ref().map_or(NATIVE_MINT, |m| m.key()).as_ref()
+        ],
+        bump
+    )]
+    pub party_b_limit: AccountInfo<'info>,
+
+    /// Mints and token accounts are omitted for native SOL legs.
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+    pub mint_b: Option<Box<InterfaceAccount<'info, Mint>>>,
 
-    #[account(mut, token::authority = party_b)]
-    pub party_b_token_src: Account<'info, TokenAccount>,
-    #[account(mut, token::authority = party_a)]
-    pub party_a_token_dest: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = party_a, token::mint = mint_a)]
+    pub party_a_token_src: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(mut, token::authority = party_b, token::mint = mint_a)]
+    pub party_b_token_dest: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(mut, token::authority = party_b, token::mint = mint_b)]
+    pub party_b_token_src: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(mut, token::authority = party_a, token::mint = mint_b)]
+    pub party_a_token_dest: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Treasury accounts are only needed for legs that are charged a fee.
+    #[account(mut, token::authority = pool.treasury, token::mint = mint_a)]
+    pub treasury_token_account_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(mut, token::authority = pool.treasury, token::mint = mint_b)]
+    pub treasury_token_account_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// CHECK: treasury wallet that receives native SOL fees
+    #[account(mut, address = pool.treasury @ OtcError::InvalidTreasuryAccount)]
+    pub treasury: Option<AccountInfo<'info>>,
+
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(amount_a: u64, amount_b: u64, expiration_ts: i64)]
+#[instruction(offer_id: u64, amount_a: u64, amount_b: u64, expiration_ts: i64)]
 pub struct CreateOffer<'info> {
     #[account(mut)]
     pub pool: Account<'info, Pool>,
@@ -718,32 +3771,59 @@ pub struct CreateOffer<'info> {
     #[account(mut)]
     pub maker: Signer<'info>,
 
+    #[account(
+        seeds = [b"partner", pool.key().as_ref(), maker.key().as_ref()],
+        bump = maker_record.bump
+    )]
+    pub maker_record: Box<Account<'info, PartnerRecord>>,
+
+    /// CHECK: maker's `PartnerLimit` for mint_a; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [
+            b"partner_limit",
+            pool.key().as_ref(),
+            maker.key().as_ref(),
+            mint_a.as_ref().map_or(NATIVE_MINT, |m| m.key()).as_ref()
+        ],
+        bump
+    )]
+    pub maker_limit: AccountInfo<'info>,
+
     #[account(
         init,
         payer = maker,
         space = Offer::LEN,
-        seeds = [b"offer", maker.key().as_ref()],
+        seeds = [b"offer", maker.key().as_ref(), &offer_id.to_le_bytes()],
         bump
     )]
     pub offer: Account<'info, Offer>,
 
-    #[account(mut, token::authority = maker, token::mint = mint_a)]
-    pub maker_token_src: Account<'info, TokenAccount>,
+    /// Token accounts and mints are omitted for native SOL legs.
+    #[account(
+        mut,
+        token::authority = maker,
+        token::mint = mint_a,
+        token::token_program = token_program_a
+    )]
+    pub maker_token_src: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     #[account(
         init,
         payer = maker,
         token::authority = offer,
         token::mint = mint_a,
-        seeds = [b"offer", maker.key().as_ref()],
+        token::token_program = token_program_a,
+        seeds = [b"escrow", offer.key().as_ref()],
         bump
     )]
-    pub escrow_account: Account<'info, TokenAccount>,
+    pub escrow_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
-    pub mint_a: Account<'info, Mint>,
-    pub mint_b: Account<'info, Mint>,
+    #[account(mint::token_program = token_program_a)]
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+    pub mint_b: Option<Box<InterfaceAccount<'info, Mint>>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program_a: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
@@ -753,22 +3833,55 @@ pub struct CancelOffer<'info> {
     #[account(mut)]
     pub pool: Account<'info, Pool>,
 
-    #[account(mut, has_one = maker)]
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = maker,
+        seeds = [b"offer", maker.key().as_ref(), &offer.offer_id.to_le_bytes()],
+        bump = offer.bump
+    )]
     pub offer: Account<'info, Offer>,
 
-    #[account(mut, token::authority = offer, token::mint = offer.mint_a)]
-    pub escrow_account: Account<'info, TokenAccount>,
+    /// Token accounts are omitted when mint_a is native SOL.
+    #[account(
+        mut,
+        token::authority = offer,
+        token::mint = offer.mint_a,
+        seeds = [b"escrow", offer.key().as_ref()],
+        bump = offer.escrow_bump
+    )]
+    pub escrow_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
+    #[account(mut)]
     pub maker: Signer<'info>,
 
     #[account(mut, token::authority = maker, token::mint = offer.mint_a)]
-    pub maker_token_dest: Account<'info, TokenAccount>,
+    pub maker_token_dest: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: maker's `PartnerLimit` for mint_a; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [b"partner_limit", pool.key().as_ref(), maker.key().as_ref(), offer.mint_a.as_ref()],
+        bump
+    )]
+    pub maker_limit: AccountInfo<'info>,
+
+    /// CHECK: maker's `PartnerRecord`; closed if the partner has been removed
+    #[account(seeds = [b"partner", pool.key().as_ref(), maker.key().as_ref()], bump)]
+    pub maker_record: AccountInfo<'info>,
 
     #[account(mut, token::authority = pool.treasury, token::mint = offer.mint_a)]
-    pub treasury_token_account: Account<'info, TokenAccount>,
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: treasury wallet that receives native SOL fees
+    #[account(mut, address = pool.treasury @ OtcError::InvalidTreasuryAccount)]
+    pub treasury: Option<AccountInfo<'info>>,
+
+    #[account(address = offer.mint_a, mint::decimals = offer.decimals_a)]
+    pub mint_a: Option<InterfaceAccount<'info, Mint>>,
 
     pub clock: Sysvar<'info, Clock>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
@@ -777,50 +3890,322 @@ pub struct AcceptOffer<'info> {
     #[account(mut)]
     pub pool: Account<'info, Pool>,
 
-    #[account(mut, has_one = maker)]
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = maker,
+        seeds = [b"offer", maker.key().as_ref(), &offer.offer_id.to_le_bytes()],
+        bump = offer.bump
+    )]
     pub offer: Account<'info, Offer>,
 
-    /// Added to satisfy has_one = maker
+    /// Added to satisfy has_one = maker; receives native SOL when mint_b is native
+    #[account(mut)]
     pub maker: AccountInfo<'info>,
 
-    #[account(signer)]
+    #[account(mut, signer)]
     pub taker: AccountInfo<'info>,
 
+    #[account(
+        seeds = [b"partner", pool.key().as_ref(), maker.key().as_ref()],
+        bump = maker_record.bump
+    )]
+    pub maker_record: Box<Account<'info, PartnerRecord>>,
+    #[account(
+        seeds = [b"partner", pool.key().as_ref(), taker.key().as_ref()],
+        bump = taker_record.bump
+    )]
+    pub taker_record: Box<Account<'info, PartnerRecord>>,
+
+    /// CHECK: maker's `PartnerStats`; uninitialized until `init_partner_stats` is called
+    #[account(
+        mut,
+        seeds = [b"partner_stats", pool.key().as_ref(), maker.key().as_ref()],
+        bump
+    )]
+    pub maker_stats: AccountInfo<'info>,
+    /// CHECK: taker's `PartnerStats`; uninitialized until `init_partner_stats` is called
+    #[account(
+        mut,
+        seeds = [b"partner_stats", pool.key().as_ref(), taker.key().as_ref()],
+        bump
+    )]
+    pub taker_stats: AccountInfo<'info>,
+
+    /// CHECK: maker's `PartnerLimit` for mint_a; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [b"partner_limit", pool.key().as_ref(), maker.key().as_ref(), offer.mint_a.as_ref()],
+        bump
+    )]
+    pub maker_limit: AccountInfo<'info>,
+    /// CHECK: taker's `PartnerLimit` for mint_b; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [b"partner_limit", pool.key().as_ref(), taker.key().as_ref(), offer.mint_b.as_ref()],
+        bump
+    )]
+    pub taker_limit: AccountInfo<'info>,
+
+    /// Mints and token accounts are omitted for native SOL legs.
+    #[account(address = offer.mint_a, mint::decimals = offer.decimals_a)]
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(address = offer.mint_b, mint::decimals = offer.decimals_b)]
+    pub mint_b: Option<Box<InterfaceAccount<'info, Mint>>>,
+
     #[account(mut, token::authority = taker, token::mint = offer.mint_b)]
-    pub taker_token_src: Account<'info, TokenAccount>,
+    pub taker_token_src: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
-    #[account(mut, token::authority = pool.treasury, token::mint = offer.mint_b)]
-    pub maker_token_dest: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = maker, token::mint = offer.mint_b)]
+    pub maker_token_dest: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
-    #[account(mut, token::authority = offer, token::mint = offer.mint_a)]
-    pub escrow_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::authority = offer,
+        token::mint = offer.mint_a,
+        seeds = [b"escrow", offer.key().as_ref()],
+        bump = offer.escrow_bump
+    )]
+    pub escrow_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     #[account(mut, token::authority = taker, token::mint = offer.mint_a)]
-    pub taker_token_dest: Account<'info, TokenAccount>,
+    pub taker_token_dest: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
+    /// Treasury accounts are only needed for mints that are charged a fee.
     #[account(mut, token::authority = pool.treasury, token::mint = offer.mint_a)]
-    pub treasury_token_account: Account<'info, TokenAccount>,
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut, token::authority = pool.treasury, token::mint = offer.mint_b)]
+    pub treasury_token_account_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// CHECK: treasury wallet that receives native SOL fees
+    #[account(mut, address = pool.treasury @ OtcError::InvalidTreasuryAccount)]
+    pub treasury: Option<AccountInfo<'info>>,
+
+    /// CHECK: pool's rebate vault for mint_b; only needed when the maker fee is a rebate
+    #[account(mut, seeds = [b"rebate_vault", pool.key().as_ref(), offer.mint_b.as_ref()], bump)]
+    pub rebate_vault: Option<AccountInfo<'info>>,
+
+    /// CHECK: PDA that owns the rebate vault token accounts
+    #[account(seeds = [b"rebate_authority", pool.key().as_ref()], bump)]
+    pub rebate_authority: Option<AccountInfo<'info>>,
 
     pub clock: Sysvar<'info, Clock>,
-    pub token_program: Program<'info, Token>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(partner: Pubkey)]
+pub struct InitPartnerStats<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(seeds = [b"partner", pool.key().as_ref(), partner.as_ref()], bump = partner_record.bump)]
+    pub partner_record: Account<'info, PartnerRecord>,
+    #[account(
+        init,
+        payer = payer,
+        space = PartnerStats::LEN,
+        seeds = [b"partner_stats", pool.key().as_ref(), partner.as_ref()],
+        bump
+    )]
+    pub partner_stats: Account<'info, PartnerStats>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RecomputePartnerTier<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        has_one = pool,
+        seeds = [b"partner_stats", pool.key().as_ref(), partner_stats.partner.as_ref()],
+        bump = partner_stats.bump
+    )]
+    pub partner_stats: Account<'info, PartnerStats>,
+}
+
+#[derive(Accounts)]
+pub struct InitRebateVault<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = mint,
+        token::authority = rebate_authority,
+        token::token_program = token_program,
+        seeds = [b"rebate_vault", pool.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub rebate_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mint::token_program = token_program)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: PDA that owns the rebate vault token accounts
+    #[account(seeds = [b"rebate_authority", pool.key().as_ref()], bump)]
+    pub rebate_authority: AccountInfo<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitNativeRebateVault<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = NativeRebateVault::LEN,
+        seeds = [b"rebate_vault", pool.key().as_ref(), NATIVE_MINT.as_ref()],
+        bump
+    )]
+    pub rebate_vault: Account<'info, NativeRebateVault>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AmendOffer<'info> {
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = maker,
+        seeds = [b"offer", maker.key().as_ref(), &offer.offer_id.to_le_bytes()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        seeds = [b"partner", pool.key().as_ref(), maker.key().as_ref()],
+        bump = maker_record.bump
+    )]
+    pub maker_record: Box<Account<'info, PartnerRecord>>,
+
+    /// CHECK: maker's `PartnerLimit` for mint_a; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [b"partner_limit", pool.key().as_ref(), maker.key().as_ref(), offer.mint_a.as_ref()],
+        bump
+    )]
+    pub maker_limit: AccountInfo<'info>,
+
+    /// Token accounts and the mint are omitted when mint_a is native SOL.
+    #[account(
+        mut,
+        token::authority = offer,
+        token::mint = offer.mint_a,
+        seeds = [b"escrow", offer.key().as_ref()],
+        bump = offer.escrow_bump
+    )]
+    pub escrow_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Source of a top-up and destination of a refund.
+    #[account(mut, token::authority = maker, token::mint = offer.mint_a)]
+    pub maker_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = offer.mint_a, mint::decimals = offer.decimals_a)]
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct ExtendOffer<'info> {
-    #[account(mut, has_one = maker)]
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = maker,
+        seeds = [b"offer", maker.key().as_ref(), &offer.offer_id.to_le_bytes()],
+        bump = offer.bump
+    )]
     pub offer: Account<'info, Offer>,
     #[account(signer)]
     pub maker: AccountInfo<'info>,
     pub pool: Account<'info, Pool>,
+    /// CHECK: maker's `PartnerRecord`; closed if the partner has been removed
+    #[account(seeds = [b"partner", pool.key().as_ref(), maker.key().as_ref()], bump)]
+    pub maker_record: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
 pub struct CloseExpiredOffer<'info> {
     #[account(mut)]
     pub pool: Account<'info, Pool>,
-    #[account(mut)]
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = maker,
+        close = maker,
+        seeds = [b"offer", maker.key().as_ref(), &offer.offer_id.to_le_bytes()],
+        bump = offer.bump
+    )]
     pub offer: Account<'info, Offer>,
+
+    /// CHECK: receives the refunded rent; checked by has_one on offer
+    #[account(mut)]
+    pub maker: AccountInfo<'info>,
+
+    /// Token accounts are omitted when mint_a is native SOL.
+    #[account(
+        mut,
+        token::authority = offer,
+        token::mint = offer.mint_a,
+        seeds = [b"escrow", offer.key().as_ref()],
+        bump = offer.escrow_bump
+    )]
+    pub escrow_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::authority = maker, token::mint = offer.mint_a)]
+    pub maker_token_dest: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: maker's `PartnerLimit` for mint_a; uninitialized when no limit is set
+    #[account(
+        mut,
+        seeds = [b"partner_limit", pool.key().as_ref(), maker.key().as_ref(), offer.mint_a.as_ref()],
+        bump
+    )]
+    pub maker_limit: AccountInfo<'info>,
+
+    /// Writable so withheld transfer fees can be harvested before the escrow closes.
+    #[account(mut, address = offer.mint_a, mint::decimals = offer.decimals_a)]
+    pub mint_a: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Treasury accounts are only needed when the pool charges a cancellation fee.
+    #[account(mut, token::authority = pool.treasury, token::mint = offer.mint_a)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: treasury wallet that receives native SOL fees
+    #[account(mut, address = pool.treasury @ OtcError::InvalidTreasuryAccount)]
+    pub treasury: Option<AccountInfo<'info>>,
+
     pub clock: Sysvar<'info, Clock>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CrankExpiredOffers<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[error_code]
@@ -863,5 +4248,238 @@ pub enum OtcError {
     InvalidExtension,
     #[msg("Expiration too long")]
     ExpirationTooLong,
+    #[msg("Invalid crank accounts")]
+    InvalidCrankAccounts,
+    #[msg("Account for trade leg not provided")]
+    MissingLegAccount,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("Invalid fee configuration")]
+    InvalidFeeConfig,
+    #[msg("Maker and taker must be different partners")]
+    SelfTrade,
+    #[msg("Tier window has not elapsed")]
+    TierWindowNotElapsed,
+    #[msg("Partner stats have no room for another mint")]
+    PartnerStatsFull,
+    #[msg("Partner is suspended")]
+    PartnerSuspended,
+    #[msg("Partner has been offboarded")]
+    PartnerOffboarded,
+    #[msg("Partner approval has expired")]
+    PartnerExpired,
+    #[msg("Partner expiry must be in the future")]
+    InvalidPartnerExpiry,
+    #[msg("Invalid partner status transition")]
+    InvalidStatusTransition,
+    #[msg("Trade exceeds the partner's single-trade limit")]
+    TradeAboveLimit,
+    #[msg("Trade exceeds the partner's 24h volume limit")]
+    DailyVolumeLimitExceeded,
+    #[msg("Offer exceeds the partner's open escrow limit")]
+    OpenEscrowLimitExceeded,
+    #[msg("No pending proposal")]
+    NoPendingProposal,
+    #[msg("Role already granted to this member")]
+    RoleAlreadyGranted,
+    #[msg("Role not granted to this member")]
+    RoleNotGranted,
+    #[msg("Role registry is full")]
+    RoleRegistryFull,
+    #[msg("Timelock is active; queue this change instead")]
+    TimelockActive,
+    #[msg("Timelock delay out of range")]
+    InvalidTimelockDelay,
+    #[msg("Change eta is earlier than the timelock delay allows")]
+    EtaTooEarly,
+    #[msg("Queued change is not executable yet")]
+    TimelockNotElapsed,
+    #[msg("Invalid pool configuration")]
+    InvalidPoolConfig,
+    #[msg("Invalid council members or threshold")]
+    InvalidCouncilConfig,
+    #[msg("Signer is not a council member")]
+    NotCouncilMember,
+    #[msg("Member already approved this proposal")]
+    AlreadyApproved,
+    #[msg("Proposal already executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal has not reached the approval threshold")]
+    ThresholdNotMet,
+    #[msg("Proposal exceeds the account or data limit")]
+    ProposalTooLarge,
+    #[msg("Accounts do not match the proposal")]
+    ProposalAccountMismatch,
+    #[msg("Pair is frozen")]
+    PairFrozen,
+    #[msg("Partner is frozen")]
+    PartnerFrozen,
+    #[msg("Emergency pause already active")]
+    EmergencyPauseActive,
+    #[msg("No active emergency pause")]
+    NoEmergencyPause,
+    #[msg("Offer is restricted to other takers")]
+    TakerNotAllowed,
+    #[msg("Too many allowed takers")]
+    TooManyAllowedTakers,
+    #[msg("Minimum fill exceeds the offer amount")]
+    InvalidFillConstraints,
+    #[msg("All-or-none offer must be filled in full")]
+    AllOrNoneFill,
+    #[msg("Fill is below the offer's minimum fill")]
+    FillBelowMinimum,
+    #[msg("Fill would leave a remainder below the offer's minimum fill")]
+    RemainderBelowMinimum,
+    #[msg("Offer's last allowed fill must take the full remainder")]
+    MaxFillsReached,
+    #[msg("Offer was modified since the expected sequence")]
+    OfferSequenceMismatch,
+    #[msg("Amount received is below min_receive_a")]
+    SlippageExceeded,
+    #[msg("Offer has used all its extensions")]
+    ExtensionLimitReached,
+    #[msg("Extension exceeds the pool's maximum offer lifetime")]
+    OfferLifetimeExceeded,
+    #[msg("Expiration is too soon")]
+    ExpirationTooSoon,
+    #[msg("Guardian has already used its emergency pause")]
+    GuardianPauseUsed,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(amount_b: u64, fill_constraints: FillConstraints) -> Offer {
+        Offer {
+            pool: Pubkey::default(),
+            maker: Pubkey::default(),
+            offer_id: 0,
+            mint_a: Pubkey::default(),
+            mint_b: Pubkey::default(),
+            original_amount_a: amount_b,
+            original_amount_b: amount_b,
+            amount_a: amount_b,
+            amount_b,
+            expiration_ts: 0,
+            fulfilled: false,
+            bump: 0,
+            escrow_bump: 0,
+            decimals_a: 0,
+            decimals_b: 0,
+            created_ts: 0,
+            allowed_takers: Vec::new(),
+            fill_constraints,
+            fill_count: 0,
+            sequence: 0,
+            extension_count: 0,
+            crank_deposit: 0,
+        }
+    }
+
+    fn constraints(all_or_none: bool, min_fill_b: u64, max_fills: u16) -> FillConstraints {
+        FillConstraints { all_or_none, min_fill_b, max_fills }
+    }
+
+    fn assert_err(result: Result<()>, expected: OtcError) {
+        assert_eq!(result.unwrap_err(), expected.into());
+    }
+
+    #[test]
+    fn single_fill_offer_must_be_taken_whole() {
+        let offer = offer(100, constraints(false, 0, 1));
+        assert_err(offer.check_fill(40), OtcError::MaxFillsReached);
+        assert!(offer.check_fill(100).is_ok());
+    }
+
+    #[test]
+    fn last_of_two_fills_must_take_the_remainder() {
+        let mut offer = offer(100, constraints(false, 0, 2));
+        assert!(offer.check_fill(40).is_ok());
+
+        offer.fill_count = 1;
+        offer.amount_b = 60;
+        assert_err(offer.check_fill(30), OtcError::MaxFillsReached);
+        assert!(offer.check_fill(60).is_ok());
+    }
+
+    #[test]
+    fn all_or_none_ignores_min_fill_for_the_whole_offer() {
+        let offer = offer(100, constraints(true, 150, 0));
+        assert_err(offer.check_fill(99), OtcError::AllOrNoneFill);
+        assert!(offer.check_fill(100).is_ok());
+    }
+
+    #[test]
+    fn min_fill_bounds_fill_and_remainder() {
+        let offer = offer(100, constraints(false, 30, 0));
+        assert_err(offer.check_fill(20), OtcError::FillBelowMinimum);
+        assert_err(offer.check_fill(80), OtcError::RemainderBelowMinimum);
+        assert!(offer.check_fill(30).is_ok());
+        assert!(offer.check_fill(70).is_ok());
+        assert!(offer.check_fill(100).is_ok());
+    }
+
+    fn limit(max_daily_volume: u64) -> PartnerLimit {
+        PartnerLimit {
+            pool: Pubkey::default(),
+            partner: Pubkey::default(),
+            mint: Pubkey::default(),
+            limits: MintLimits {
+                max_single_trade: u64::MAX,
+                max_daily_volume,
+                max_open_escrow: u64::MAX,
+            },
+            last_hour: 0,
+            hourly_volume: [0; LIMIT_WINDOW_HOURS],
+            open_escrow: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn volume_rolls_off_after_24_hours() {
+        let hour = 3600;
+        let mut limit = limit(100);
+        limit.record_volume(60, 100 * hour).unwrap();
+        limit.record_volume(30, 105 * hour + 59).unwrap();
+        assert_err(limit.record_volume(20, 123 * hour), OtcError::DailyVolumeLimitExceeded);
+
+        // 24 hours after the first trade its bucket is cleared; the later one still counts.
+        limit.record_volume(70, 124 * hour).unwrap();
+        assert_eq!(limit.hourly_volume.iter().sum::<u64>(), 100);
+        assert_eq!(limit.last_hour, 124);
+    }
+
+    #[test]
+    fn long_gap_clears_every_bucket() {
+        let hour = 3600;
+        let mut limit = limit(100);
+        for h in 0..LIMIT_WINDOW_HOURS as i64 {
+            limit.record_volume(4, (200 + h) * hour).unwrap();
+        }
+        assert_err(limit.record_volume(5, 223 * hour), OtcError::DailyVolumeLimitExceeded);
+
+        limit.record_volume(100, 300 * hour).unwrap();
+        assert_eq!(limit.hourly_volume.iter().sum::<u64>(), 100);
+        assert_eq!(limit.last_hour, 300);
+    }
+
+    #[test]
+    fn overdue_tier_window_counts_one_window_of_volume() {
+        let mint = Pubkey::new_unique();
+        let day = 24 * 3600;
+        let stats = PartnerStats {
+            pool: Pubkey::default(),
+            partner: Pubkey::default(),
+            tier: 0,
+            window_start_ts: 0,
+            volumes: vec![MintVolume { mint, cumulative_volume: 1_000, window_volume: 1_000 }],
+            bump: 0,
+        };
+        assert_eq!(stats.volume_per_window(&mint, 30 * day, 30 * day), 1_000);
+        assert_eq!(stats.volume_per_window(&mint, 75 * day, 30 * day), 400);
+        assert_eq!(stats.volume_per_window(&Pubkey::new_unique(), 75 * day, 30 * day), 0);
+    }
+}
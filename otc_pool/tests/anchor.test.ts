@@ -6,12 +6,12 @@ describe("OTC Pool Tests", () => {
     const poolKeypair = new web3.Keypair();
 
     // Set parameters for the pool initialization
-    const maxPartners = 5; // Maximum number of partners (u8)
+    const maxPartners = 5; // Maximum number of partners (u32)
     const feeBps = 100; // Fee in basis points (u16)
     const treasury = new web3.Keypair().publicKey; // Treasury public key (PublicKey)
     const minSwapAmount = new BN(1000); // Minimum swap amount (u64)
     const maxExpirationSecs = new BN(3600); // Maximum expiration time in seconds (i64)
-    const partners = []; // Empty array for initial partners (PublicKey[])
+    const legacyPartners = []; // New pools hold no legacy partners (PublicKey[])
     const initialWhitelistedMints = [new web3.Keypair().publicKey]; // Example whitelisted mints (PublicKey[])
 
     // Send the transaction to initialize the pool
@@ -49,7 +49,7 @@ describe("OTC Pool Tests", () => {
       minSwapAmount: poolAccount.minSwapAmount.toString(),
       maxExpirationSecs: poolAccount.maxExpirationSecs.toString(),
       initialWhitelistedMints: poolAccount.whitelistedMints.map((mint: web3.PublicKey) => mint.toBase58()),
-      partners: poolAccount.partners.map((p: web3.PublicKey) => p.toBase58()),
+      legacyPartners: poolAccount.legacyPartners.map((p: web3.PublicKey) => p.toBase58()),
     });
 
     // Validate the parameters
@@ -60,6 +60,6 @@ describe("OTC Pool Tests", () => {
     assert(minSwapAmount.eq(new BN(poolAccount.minSwapAmount)));
     assert(maxExpirationSecs.eq(new BN(poolAccount.maxExpirationSecs)));
     assert.deepStrictEqual(poolAccount.whitelistedMints.map((mint: web3.PublicKey) => mint.toBase58()), initialWhitelistedMints.map((mint: web3.PublicKey) => mint.toBase58()));
-    assert.deepStrictEqual(poolAccount.partners, partners);
+    assert.deepStrictEqual(poolAccount.legacyPartners, legacyPartners);
   });
 });